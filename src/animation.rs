@@ -0,0 +1,105 @@
+// Copyright 2019, Sjors van Gelderen
+
+use crate::metasprite::{
+    Metasprite,
+    Part,
+};
+
+pub const NTSC_FRAME_RATE: f64 = 60.0988;
+
+// Values of the kind table
+pub const CEL_METASPRITE: u8 = 0;
+pub const CEL_TILE: u8 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cel {
+    Metasprite(usize),
+    Tile { tile: u8, attributes: u8 },
+}
+
+// Durations are counted in NTSC frames
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frame {
+    pub cel: Cel,
+    pub duration: u8,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Animation {
+    pub frames: Vec<Frame>,
+}
+
+impl Animation {
+    pub fn get_length(&self) -> usize {
+        self.frames.iter().map(|x| x.duration as usize).sum()
+    }
+
+    // The animation loops, so any tick maps to a frame
+    pub fn get_frame_at(&self, tick: usize) -> Option<usize> {
+        let length = self.get_length();
+
+        if length == 0 {
+            return None;
+        }
+
+        let mut remaining = tick % length;
+
+        self.frames.iter().position(|x| {
+            if remaining < x.duration as usize {
+                true
+            }
+            else {
+                remaining -= x.duration as usize;
+                false
+            }
+        })
+    }
+
+    // Tiles are shown as a metasprite of a single part at the origin
+    pub fn get_metasprite(&self, frame: usize, metasprites: &[Metasprite]) -> Metasprite {
+        match self.frames[frame].cel {
+            Cel::Metasprite(which) => metasprites.get(which).cloned().unwrap_or(Metasprite::new(vec![])),
+            Cel::Tile { tile, attributes } => Metasprite::new(vec![Part { dx: 0, dy: 0, tile, attributes }]),
+        }
+    }
+
+    // Holds the metasprite or tile index of every frame, the kind table tells which of the two it is
+    pub fn get_frame_table(&self) -> Vec<u8> {
+        self.frames.iter().map(|x| match x.cel {
+            Cel::Metasprite(which) => which as u8,
+            Cel::Tile { tile, .. } => tile,
+        }).collect()
+    }
+
+    pub fn get_kind_table(&self) -> Vec<u8> {
+        self.frames.iter().map(|x| match x.cel {
+            Cel::Metasprite(_) => CEL_METASPRITE,
+            Cel::Tile { .. } => CEL_TILE,
+        }).collect()
+    }
+
+    // Metasprites carry their own attributes, so their frames hold zero
+    pub fn get_attribute_table(&self) -> Vec<u8> {
+        self.frames.iter().map(|x| match x.cel {
+            Cel::Metasprite(_) => 0,
+            Cel::Tile { attributes, .. } => attributes,
+        }).collect()
+    }
+
+    pub fn get_duration_table(&self) -> Vec<u8> {
+        self.frames.iter().map(|x| x.duration).collect()
+    }
+
+    // GIF delays are in hundredths of a second, rounding is carried over so the total stays in sync
+    pub fn get_gif_delays(&self) -> Vec<u16> {
+        let mut elapsed = 0usize;
+
+        self.frames.iter().map(|x| {
+            let start = (elapsed as f64 * 100.0 / NTSC_FRAME_RATE).round() as u16;
+            elapsed += x.duration as usize;
+            let end = (elapsed as f64 * 100.0 / NTSC_FRAME_RATE).round() as u16;
+
+            end - start
+        }).collect()
+    }
+}
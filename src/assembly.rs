@@ -0,0 +1,100 @@
+// Copyright 2019, Sjors van Gelderen
+
+// Sources for the ca65 assembler, every label is exported and listed in a shared include file
+
+use std::fmt;
+
+// Labels are followed by a size constant in capitals, such as PALETTE_SIZE for palette
+pub struct Source {
+    segment: String,
+    lines: Vec<String>,
+    labels: Vec<String>,
+    constants: Vec<(String, usize)>,
+}
+
+impl Source {
+    pub fn new(segment: &str) -> Self {
+        Self {
+            segment: segment.to_string(),
+            lines: vec![],
+            labels: vec![],
+            constants: vec![],
+        }
+    }
+
+    pub fn add_bytes(&mut self, label: &str, bytes: &[u8]) {
+        self.add_label(label, bytes.len());
+
+        for row in bytes.chunks(16) {
+            let row: Vec<String> = row.iter().map(|x| format!("${:02X}", x)).collect();
+            self.lines.push(format!("    .byte {}", row.join(",")));
+        }
+    }
+
+    // The file is included as it is, relative to the source
+    pub fn add_incbin(&mut self, label: &str, path: &str, size: usize) {
+        self.add_label(label, size);
+        self.lines.push(format!("    .incbin \"{}\"", path));
+    }
+
+    // A table of the addresses of other labels, with a constant for the number of entries
+    pub fn add_addresses(&mut self, label: &str, labels: &[String]) {
+        self.add_label(label, labels.len() * 2);
+        self.constants.push((format!("{}_COUNT", label.to_uppercase()), labels.len()));
+
+        for row in labels.chunks(8) {
+            self.lines.push(format!("    .addr {}", row.join(",")));
+        }
+    }
+
+    fn add_label(&mut self, label: &str, size: usize) {
+        self.lines.push(String::new());
+        self.lines.push(format!("{}:", label));
+        self.labels.push(label.to_string());
+        self.constants.push((format!("{}_SIZE", label.to_uppercase()), size));
+    }
+}
+
+// Exports every label before the constants and the lines of the segment
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for label in self.labels.iter() {
+            writeln!(f, ".export {}", label)?;
+        }
+
+        writeln!(f)?;
+
+        for (name, value) in self.constants.iter() {
+            writeln!(f, "{} = {}", name, value)?;
+        }
+
+        writeln!(f, "\n.segment \"{}\"", self.segment)?;
+
+        for line in self.lines.iter() {
+            writeln!(f, "{}", line)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Imports every label of the sources and repeats their constants, guarded against being included twice
+pub fn get_include(name: &str, sources: &[&Source]) -> String {
+    let guard = format!("{}_INC", name.to_uppercase());
+    let mut text = format!(".ifndef {}\n{} = 1\n", guard, guard);
+
+    for source in sources {
+        text += "\n";
+
+        for label in source.labels.iter() {
+            text += &format!(".import {}\n", label);
+        }
+
+        for (name, value) in source.constants.iter() {
+            text += &format!("{} = {}\n", name, value);
+        }
+    }
+
+    text += "\n.endif\n";
+    text
+}
@@ -1,13 +1,38 @@
 // Copyright 2019, Sjors van Gelderen
 
-// pub struct AttributeTable {
-    
-// }
+// Every byte covers 4 by 4 tiles, with two bits for each 2 by 2 quadrant
+#[derive(Clone, Copy)]
+pub struct AttributeTable {
+    pub bytes: [u8; 64],
+}
 
-// impl AttributeTable {
-//     pub fn zero() -> AttributeTable {
-//         AttributeTable {
+impl AttributeTable {
+    pub fn zero() -> AttributeTable {
+        AttributeTable {
+            bytes: [0u8; 64],
+        }
+    }
 
-//         }
-//     }
-// }
\ No newline at end of file
+    pub fn get_sub_palette(&self, tile_x: usize, tile_y: usize) -> u8 {
+        let (index, shift) = Self::get_index_and_shift(tile_x, tile_y);
+
+        self.bytes[index] >> shift & 0b11
+    }
+
+    // Returns the previous sub-palette
+    pub fn set_sub_palette(&mut self, tile_x: usize, tile_y: usize, sub_palette: u8) -> u8 {
+        let (index, shift) = Self::get_index_and_shift(tile_x, tile_y);
+        let previous = self.bytes[index] >> shift & 0b11;
+
+        self.bytes[index] = self.bytes[index] & !(0b11 << shift) | (sub_palette & 0b11) << shift;
+
+        previous
+    }
+
+    fn get_index_and_shift(tile_x: usize, tile_y: usize) -> (usize, usize) {
+        let index = tile_y / 4 * 8 + tile_x / 4;
+        let shift = tile_y % 4 / 2 * 4 + tile_x % 4 / 2 * 2;
+
+        (index, shift)
+    }
+}
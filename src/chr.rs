@@ -0,0 +1,304 @@
+// Copyright 2019, Sjors van Gelderen
+
+// Operations on character data that do not depend on the GPU
+// Pixels hold both pages side by side, 16 by 16 tiles each
+
+pub const SHEET_WIDTH: usize = 256;
+pub const SHEET_HEIGHT: usize = 128;
+
+pub fn get_tile_index(x: usize, y: usize) -> usize {
+    let page = x / 128;
+
+    page * 256 + y / 8 * 16 + x % 128 / 8
+}
+
+pub fn get_tile_origin(tile: usize) -> (usize, usize) {
+    let page = tile / 256;
+    let i = tile % 256;
+
+    (page * 128 + i % 16 * 8, i / 16 * 8)
+}
+
+pub fn is_on_sheet(x: i32, y: i32) -> bool {
+    x >= 0 && y >= 0 && (x as usize) < SHEET_WIDTH && (y as usize) < SHEET_HEIGHT
+}
+
+pub fn encode_tile(pixels: &[u8; 32768], tile: usize) -> [u8; 16] {
+    let (tile_x, tile_y) = get_tile_origin(tile);
+    let mut planes: [u8; 16] = [0u8; 16];
+
+    for y in 0..8 {
+        for x in 0..8 {
+            let pixel = pixels[(tile_y + y) * SHEET_WIDTH + tile_x + x];
+            let bit = 0b10000000 >> x;
+
+            if pixel & 1 > 0 {
+                planes[y] |= bit;
+            }
+
+            if pixel & 2 > 0 {
+                planes[y + 8] |= bit;
+            }
+        }
+    }
+
+    planes
+}
+
+pub fn encode_tiles(pixels: &[u8; 32768], bytes: &mut [u8; 8192], tiles: &[usize]) {
+    for tile in tiles {
+        bytes[tile * 16..tile * 16 + 16].copy_from_slice(&encode_tile(pixels, *tile));
+    }
+}
+
+// Returns the previous color of the pixel
+pub fn set_pixel(pixels: &mut [u8; 32768], bytes: &mut [u8; 8192], x: usize, y: usize, color: u8) -> u8 {
+    let index = y * SHEET_WIDTH + x;
+    let previous = pixels[index];
+
+    if previous != color {
+        pixels[index] = color;
+        encode_tiles(pixels, bytes, &[get_tile_index(x, y)]);
+    }
+
+    previous
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FillMode {
+    Tile,
+    Sheet,
+}
+
+// Fills the 4-connected region of the color under the starting pixel and returns the changed pixels
+pub fn flood_fill(
+    pixels: &mut [u8; 32768],
+    bytes: &mut [u8; 8192],
+    x: usize,
+    y: usize,
+    color: u8,
+    mode: FillMode
+) -> Vec<(usize, usize)> {
+    let target = pixels[y * SHEET_WIDTH + x];
+
+    if target == color {
+        return vec![];
+    }
+
+    let (x0, y0, x1, y1) = match mode {
+        FillMode::Tile => (x / 8 * 8, y / 8 * 8, x / 8 * 8 + 8, y / 8 * 8 + 8),
+        FillMode::Sheet => (0, 0, SHEET_WIDTH, SHEET_HEIGHT),
+    };
+
+    let mut filled = vec![];
+    let mut pending = vec![(x, y)];
+
+    while let Some((x, y)) = pending.pop() {
+        let index = y * SHEET_WIDTH + x;
+
+        if pixels[index] != target {
+            continue;
+        }
+
+        pixels[index] = color;
+        filled.push((x, y));
+
+        if x > x0 { pending.push((x - 1, y)); }
+        if x + 1 < x1 { pending.push((x + 1, y)); }
+        if y > y0 { pending.push((x, y - 1)); }
+        if y + 1 < y1 { pending.push((x, y + 1)); }
+    }
+
+    let mut tiles: Vec<usize> = filled.iter().map(|&(x, y)| get_tile_index(x, y)).collect();
+    tiles.sort();
+    tiles.dedup();
+
+    encode_tiles(pixels, bytes, &tiles);
+
+    filled
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transform {
+    FlipHorizontal,
+    FlipVertical,
+    RotateClockwise,
+    RotateCounterClockwise,
+    // Wraps around within the region
+    Shift(i32, i32),
+    Invert,
+    // Replaces every color index with the one at its position
+    Remap([u8; 4]),
+}
+
+// Returns the new color of every pixel in the region, which is given in pixels
+// Regions that are not square are rotated per tile, so tiles stay in place
+pub fn get_transformed(
+    pixels: &[u8; 32768],
+    region: (usize, usize, usize, usize),
+    transform: Transform
+) -> Vec<(usize, usize, u8)> {
+    let (x0, y0, width, height) = region;
+
+    if width != height {
+        if let Transform::RotateClockwise | Transform::RotateCounterClockwise = transform {
+            return (0..width / 8 * height / 8).flat_map(|i| {
+                let tile_region = (x0 + i % (width / 8) * 8, y0 + i / (width / 8) * 8, 8, 8);
+                get_transformed(pixels, tile_region, transform)
+            }).collect();
+        }
+    }
+
+    let get = |x: usize, y: usize| pixels[(y0 + y) * SHEET_WIDTH + x0 + x];
+    let wrap = |value: i32, size: usize| ((value % size as i32 + size as i32) % size as i32) as usize;
+
+    (0..width * height).map(|i| {
+        let (x, y) = (i % width, i / width);
+
+        let color = match transform {
+            Transform::FlipHorizontal => get(width - 1 - x, y),
+            Transform::FlipVertical => get(x, height - 1 - y),
+            Transform::RotateClockwise => get(y, width - 1 - x),
+            Transform::RotateCounterClockwise => get(width - 1 - y, x),
+            Transform::Shift(dx, dy) => get(wrap(x as i32 - dx, width), wrap(y as i32 - dy, height)),
+            Transform::Invert => 3 - get(x, y),
+            Transform::Remap(mapping) => mapping[get(x, y) as usize],
+        };
+
+        (x0 + x, y0 + y, color)
+    }).collect()
+}
+
+// Maps every tile of a page to its new slot when one tile is moved and the tiles in between shift over
+pub fn get_move_mapping(from: usize, to: usize) -> [u8; 256] {
+    let mut mapping = [0u8; 256];
+
+    for (i, x) in mapping.iter_mut().enumerate() {
+        *x = if i == from {
+            to
+        }
+        else if from < to && i > from && i <= to {
+            i - 1
+        }
+        else if to < from && i >= to && i < from {
+            i + 1
+        }
+        else {
+            i
+        } as u8;
+    }
+
+    mapping
+}
+
+pub fn get_identity_mapping() -> [u8; 256] {
+    let mut mapping = [0u8; 256];
+
+    for (i, x) in mapping.iter_mut().enumerate() {
+        *x = i as u8;
+    }
+
+    mapping
+}
+
+pub fn get_swap_mapping(a: usize, b: usize) -> [u8; 256] {
+    let mut mapping = get_identity_mapping();
+
+    mapping.swap(a, b);
+    mapping
+}
+
+// Returns the new color of every pixel on the page once each tile is placed in its new slot
+pub fn get_remapped(pixels: &[u8; 32768], page: usize, mapping: &[u8; 256]) -> Vec<(usize, usize, u8)> {
+    (0..256).flat_map(|tile| {
+        let (from_x, from_y) = get_tile_origin(page * 256 + tile);
+        let (to_x, to_y) = get_tile_origin(page * 256 + mapping[tile] as usize);
+
+        (0..64).map(move |i| {
+            let (x, y) = (i % 8, i / 8);
+            (to_x + x, to_y + y, pixels[(from_y + y) * SHEET_WIDTH + from_x + x])
+        })
+    }).collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Flip {
+    None,
+    Horizontal,
+    Vertical,
+    Both,
+}
+
+pub fn get_tile_pixels(pixels: &[u8; 32768], tile: usize) -> [u8; 64] {
+    let (origin_x, origin_y) = get_tile_origin(tile);
+    let mut tile_pixels = [0u8; 64];
+
+    for (i, x) in tile_pixels.iter_mut().enumerate() {
+        *x = pixels[(origin_y + i / 8) * SHEET_WIDTH + origin_x + i % 8];
+    }
+
+    tile_pixels
+}
+
+fn get_flipped(tile_pixels: &[u8; 64], flip: Flip) -> [u8; 64] {
+    let mut flipped = [0u8; 64];
+
+    for (i, x) in flipped.iter_mut().enumerate() {
+        let (x0, y0) = (i % 8, i / 8);
+        let (x1, y1) = match flip {
+            Flip::None => (x0, y0),
+            Flip::Horizontal => (7 - x0, y0),
+            Flip::Vertical => (x0, 7 - y0),
+            Flip::Both => (7 - x0, 7 - y0),
+        };
+
+        *x = tile_pixels[y1 * 8 + x1];
+    }
+
+    flipped
+}
+
+// Finds the tiles on the page that repeat an earlier tile, returned as the duplicate, the original and
+// how the original has to be flipped to match
+pub fn find_duplicates(pixels: &[u8; 32768], page: usize, flips: bool) -> Vec<(usize, usize, Flip)> {
+    let tiles: Vec<[u8; 64]> = (0..256).map(|x| get_tile_pixels(pixels, page * 256 + x)).collect();
+    let candidates = if flips {
+        vec![Flip::None, Flip::Horizontal, Flip::Vertical, Flip::Both]
+    }
+    else {
+        vec![Flip::None]
+    };
+
+    let mut duplicates: Vec<(usize, usize, Flip)> = vec![];
+
+    for tile in 1..256 {
+        let original = (0..tile)
+            // Only originals survive, so duplicates are not matched against each other
+            .filter(|x| !duplicates.iter().any(|y| y.0 == *x))
+            .find_map(|x| {
+                candidates.iter()
+                    .find(|&&flip| get_flipped(&tiles[x], flip)[..] == tiles[tile][..])
+                    .map(|&flip| (x, flip))
+            });
+
+        if let Some((original, flip)) = original {
+            duplicates.push((tile, original, flip));
+        }
+    }
+
+    duplicates
+}
+
+// Maps a pixel of the view to the sheet, where the tall arrangement shows the pairs of 8x16 sprites
+// above each other, even tiles in the upper and odd tiles in the lower row of each pair of rows
+pub fn get_sheet_position(x: usize, y: usize, tall: bool) -> (usize, usize) {
+    if !tall {
+        return (x, y);
+    }
+
+    let (column, row) = (x % 128 / 8, y / 8);
+    let tile = row / 2 * 32 + column * 2 + row % 2;
+    let (origin_x, origin_y) = get_tile_origin(x / 128 * 256 + tile);
+
+    (origin_x + x % 8, origin_y + y % 8)
+}
@@ -0,0 +1,113 @@
+// Copyright 2019, Sjors van Gelderen
+
+use crate::chr;
+use crate::nametable::{
+    self,
+    Nametable,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Area {
+    PatternTable,
+    Nametable,
+}
+
+// A rectangle of tiles, in tile coordinates of the sheet or the nametable
+#[derive(Clone, Copy, Debug)]
+pub struct Selection {
+    pub area: Area,
+    pub from: (usize, usize),
+    pub to: (usize, usize),
+}
+
+impl Selection {
+    // Returns the top left tile and the size in tiles
+    pub fn get_bounds(&self) -> (usize, usize, usize, usize) {
+        let (x0, x1) = (self.from.0.min(self.to.0), self.from.0.max(self.to.0));
+        let (y0, y1) = (self.from.1.min(self.to.1), self.from.1.max(self.to.1));
+
+        (x0, y0, x1 - x0 + 1, y1 - y0 + 1)
+    }
+
+    // The outline in pixels, used to show the selection
+    pub fn get_outline(&self) -> Vec<(usize, usize)> {
+        let (x, y, width, height) = self.get_bounds();
+        let (x0, y0) = (x * 8, y * 8);
+        let (x1, y1) = (x0 + width * 8 - 1, y0 + height * 8 - 1);
+
+        let horizontal = (x0..=x1).flat_map(|x| vec![(x, y0), (x, y1)]);
+        let vertical = (y0..=y1).flat_map(|y| vec![(x0, y), (x1, y)]);
+
+        horizontal.chain(vertical).collect()
+    }
+}
+
+// Holds both the pixels and the tile indices so it can be pasted into either view
+pub struct Clipboard {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+    pub tiles: Vec<u8>,
+}
+
+impl Clipboard {
+    pub fn from_pattern_table(pixels: &[u8; 32768], selection: &Selection) -> Self {
+        let (x0, y0, width, height) = selection.get_bounds();
+
+        let tiles = (0..width * height)
+            .map(|i| (chr::get_tile_index((x0 + i % width) * 8, (y0 + i / width) * 8) % 256) as u8)
+            .collect();
+
+        let pixels = (0..width * height * 64).map(|i| {
+            let (x, y) = (i % (width * 8), i / (width * 8));
+            pixels[(y0 * 8 + y) * chr::SHEET_WIDTH + x0 * 8 + x]
+        }).collect();
+
+        Self {
+            width,
+            height,
+            pixels,
+            tiles,
+        }
+    }
+
+    // The pixels are taken from the tiles on the given page
+    pub fn from_nametable(nametable: &Nametable, pixels: &[u8; 32768], page: usize, selection: &Selection) -> Self {
+        let (x0, y0, width, height) = selection.get_bounds();
+
+        let tiles: Vec<u8> = (0..width * height)
+            .map(|i| nametable.get_tile(x0 + i % width, y0 + i / width))
+            .collect();
+
+        let pixels = (0..width * height * 64).map(|i| {
+            let (x, y) = (i % (width * 8), i / (width * 8));
+            let tile = page * 256 + tiles[y / 8 * width + x / 8] as usize;
+            let (origin_x, origin_y) = chr::get_tile_origin(tile);
+
+            pixels[(origin_y + y % 8) * chr::SHEET_WIDTH + origin_x + x % 8]
+        }).collect();
+
+        Self {
+            width,
+            height,
+            pixels,
+            tiles,
+        }
+    }
+
+    // Pixels on the sheet with the tile at the given position as the top left, clipped to the sheet
+    pub fn get_sheet_pixels(&self, tile_x: usize, tile_y: usize) -> Vec<(usize, usize, u8)> {
+        (0..self.pixels.len())
+            .map(|i| (tile_x * 8 + i % (self.width * 8), tile_y * 8 + i / (self.width * 8), self.pixels[i]))
+            .filter(|&(x, y, _)| x < chr::SHEET_WIDTH && y < chr::SHEET_HEIGHT)
+            .collect()
+    }
+
+    // Tiles in the nametable with the tile at the given position as the top left, clipped to the nametable
+    pub fn get_nametable_tiles(&self, tile_x: usize, tile_y: usize) -> Vec<(usize, usize, u8)> {
+        (0..self.tiles.len())
+            .map(|i| (tile_x + i % self.width, tile_y + i / self.width, self.tiles[i]))
+            .filter(|&(x, y, _)| x < nametable::WIDTH && y < nametable::HEIGHT)
+            .collect()
+    }
+}
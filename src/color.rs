@@ -0,0 +1,220 @@
+// Copyright 2019, Sjors van Gelderen
+
+// Matches arbitrary colors against the usable entries of a master palette
+
+use crate::palette;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Metric {
+    Rgb,
+    OkLab,
+    Ciede2000,
+}
+
+pub struct ColorMatcher {
+    pub metric: Metric,
+    candidates: Vec<(u8, [f32; 3])>,
+}
+
+impl ColorMatcher {
+    pub fn new(colors: &[u8; 192], metric: Metric) -> Self {
+        Self::from_color_indices(colors, &get_candidates(colors), metric)
+    }
+
+    // Only matches against the given colors, earlier ones win ties
+    pub fn from_color_indices(colors: &[u8; 192], color_indices: &[u8], metric: Metric) -> Self {
+        let candidates = color_indices.iter().map(|x| {
+            let index = *x as usize * 3;
+            let rgb = [colors[index], colors[index + 1], colors[index + 2]];
+
+            (*x, get_coordinates(rgb, metric))
+        }).collect();
+
+        Self {
+            metric,
+            candidates,
+        }
+    }
+
+    pub fn get_nearest(&self, rgb: [u8; 3]) -> u8 {
+        let coordinates = get_coordinates(rgb, self.metric);
+        let mut nearest = (0x0F, std::f32::MAX);
+
+        for (color_index, candidate) in self.candidates.iter() {
+            let distance = get_distance(coordinates, *candidate, self.metric);
+
+            if distance < nearest.1 {
+                nearest = (*color_index, distance);
+            }
+        }
+
+        nearest.0
+    }
+}
+
+// $0F goes first as the canonical black, then walking backwards favours $30 over the duplicate white at $20
+pub fn get_candidates(colors: &[u8; 192]) -> Vec<u8> {
+    let mut candidates: Vec<u8> = vec![];
+
+    for x in std::iter::once(0x0F).chain((0..64).rev()) {
+        if (palette::is_black(x) && x != 0x0F) || (x == 0x0F && !candidates.is_empty()) {
+            continue;
+        }
+
+        let index = x as usize * 3;
+        let is_duplicate = candidates.iter().any(|y| {
+            let other = *y as usize * 3;
+            colors[index..index + 3] == colors[other..other + 3]
+        });
+
+        if !is_duplicate {
+            candidates.push(x);
+        }
+    }
+
+    candidates
+}
+
+fn get_coordinates(rgb: [u8; 3], metric: Metric) -> [f32; 3] {
+    match metric {
+        Metric::Rgb => [rgb[0] as f32, rgb[1] as f32, rgb[2] as f32],
+        Metric::OkLab => get_oklab(rgb),
+        Metric::Ciede2000 => get_cielab(rgb),
+    }
+}
+
+fn get_distance(a: [f32; 3], b: [f32; 3], metric: Metric) -> f32 {
+    match metric {
+        Metric::Rgb | Metric::OkLab => {
+            (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)
+        },
+        Metric::Ciede2000 => get_ciede2000(a, b),
+    }
+}
+
+fn get_linear(channel: u8) -> f32 {
+    let c = channel as f32 / 255.0;
+
+    if c <= 0.04045 {
+        c / 12.92
+    }
+    else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn get_oklab(rgb: [u8; 3]) -> [f32; 3] {
+    let (r, g, b) = (get_linear(rgb[0]), get_linear(rgb[1]), get_linear(rgb[2]));
+
+    let l = (0.412_221_47 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+    let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+    let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+
+    [
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    ]
+}
+
+// CIE L*a*b* relative to the D65 white point
+fn get_cielab(rgb: [u8; 3]) -> [f32; 3] {
+    let (r, g, b) = (get_linear(rgb[0]), get_linear(rgb[1]), get_linear(rgb[2]));
+
+    let x = (0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b) / 0.950_47;
+    let y = 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b;
+    let z = (0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b) / 1.088_83;
+
+    let f = |t: f32| {
+        let delta: f32 = 6.0 / 29.0;
+
+        if t > delta.powi(3) {
+            t.cbrt()
+        }
+        else {
+            t / (3.0 * delta * delta) + 4.0 / 29.0
+        }
+    };
+
+    [
+        116.0 * f(y) - 16.0,
+        500.0 * (f(x) - f(y)),
+        200.0 * (f(y) - f(z)),
+    ]
+}
+
+fn get_hue(a: f32, b: f32) -> f32 {
+    if a == 0.0 && b == 0.0 {
+        0.0
+    }
+    else {
+        let hue = b.atan2(a).to_degrees();
+
+        if hue < 0.0 { hue + 360.0 } else { hue }
+    }
+}
+
+fn get_ciede2000(lab1: [f32; 3], lab2: [f32; 3]) -> f32 {
+    let [l1, a1, b1] = lab1;
+    let [l2, a2, b2] = lab2;
+
+    let c_bar = ((a1 * a1 + b1 * b1).sqrt() + (a2 * a2 + b2 * b2).sqrt()) / 2.0;
+    let g = 0.5 * (1.0 - (c_bar.powi(7) / (c_bar.powi(7) + 25f32.powi(7))).sqrt());
+
+    let a1 = (1.0 + g) * a1;
+    let a2 = (1.0 + g) * a2;
+    let c1 = (a1 * a1 + b1 * b1).sqrt();
+    let c2 = (a2 * a2 + b2 * b2).sqrt();
+    let h1 = get_hue(a1, b1);
+    let h2 = get_hue(a2, b2);
+
+    let delta_l = l2 - l1;
+    let delta_c = c2 - c1;
+    let delta_h = if c1 * c2 == 0.0 {
+        0.0
+    }
+    else if (h2 - h1).abs() <= 180.0 {
+        h2 - h1
+    }
+    else if h2 - h1 > 180.0 {
+        h2 - h1 - 360.0
+    }
+    else {
+        h2 - h1 + 360.0
+    };
+    let delta_h = 2.0 * (c1 * c2).sqrt() * (delta_h / 2.0).to_radians().sin();
+
+    let l_bar = (l1 + l2) / 2.0;
+    let c_bar = (c1 + c2) / 2.0;
+    let h_bar = if c1 * c2 == 0.0 {
+        h1 + h2
+    }
+    else if (h1 - h2).abs() <= 180.0 {
+        (h1 + h2) / 2.0
+    }
+    else if h1 + h2 < 360.0 {
+        (h1 + h2 + 360.0) / 2.0
+    }
+    else {
+        (h1 + h2 - 360.0) / 2.0
+    };
+
+    let t = 1.0
+        - 0.17 * (h_bar - 30.0).to_radians().cos()
+        + 0.24 * (2.0 * h_bar).to_radians().cos()
+        + 0.32 * (3.0 * h_bar + 6.0).to_radians().cos()
+        - 0.20 * (4.0 * h_bar - 63.0).to_radians().cos();
+
+    let delta_theta = 30.0 * (-((h_bar - 275.0) / 25.0).powi(2)).exp();
+    let r_c = 2.0 * (c_bar.powi(7) / (c_bar.powi(7) + 25f32.powi(7))).sqrt();
+    let s_l = 1.0 + 0.015 * (l_bar - 50.0).powi(2) / (20.0 + (l_bar - 50.0).powi(2)).sqrt();
+    let s_c = 1.0 + 0.045 * c_bar;
+    let s_h = 1.0 + 0.015 * c_bar * t;
+    let r_t = -(2.0 * delta_theta).to_radians().sin() * r_c;
+
+    let l = delta_l / s_l;
+    let c = delta_c / s_c;
+    let h = delta_h / s_h;
+
+    (l * l + c * c + h * h + r_t * c * h).max(0.0).sqrt()
+}
@@ -0,0 +1,78 @@
+// Copyright 2019, Sjors van Gelderen
+
+use crate::palette;
+
+// Fades step through the rows of the palette, one row of $10 at a time
+pub enum FadeTarget {
+    Black,
+    White,
+}
+
+// Once a color would turn black it goes straight to $0F, which also keeps $1D from stepping to $0D
+pub fn darken(color_index: u8) -> u8 {
+    if color_index < 0x10 || palette::is_black(color_index) || palette::is_black(color_index - 0x10) {
+        0x0F
+    }
+    else {
+        color_index - 0x10
+    }
+}
+
+pub fn brighten(color_index: u8) -> u8 {
+    if palette::is_black(color_index) {
+        0x00
+    }
+    else if color_index >= 0x30 {
+        0x30
+    }
+    else {
+        color_index + 0x10
+    }
+}
+
+// Returns the requested number of steps, not including the starting colors
+pub fn get_fade(color_indices: &[u8; 26], target: FadeTarget, steps: usize) -> Vec<[u8; 26]> {
+    let step: fn(u8) -> u8 = match target {
+        FadeTarget::Black => darken,
+        FadeTarget::White => brighten,
+    };
+
+    let mut current = *color_indices;
+
+    (0..steps).map(|_| {
+        for x in current.iter_mut() {
+            *x = step(*x);
+        }
+
+        current
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fades_only_produce_canonical_black() {
+        for start in 0..64u8 {
+            let color_indices = [start; 26];
+
+            for target in vec![FadeTarget::Black, FadeTarget::White] {
+                for step in get_fade(&color_indices, target, 8) {
+                    assert!(step.iter().all(|x| *x != 0x0D), "fading ${:02X} produced $0D", start);
+                    assert!(
+                        step.iter().all(|x| *x == 0x0F || !palette::is_black(*x)),
+                        "fading ${:02X} produced a duplicate black", start
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn darkening_2d_and_3d_skips_1d_and_0d() {
+        assert_eq!(darken(0x3D), 0x2D);
+        assert_eq!(darken(0x2D), 0x0F);
+        assert_eq!(darken(0x1D), 0x0F);
+    }
+}
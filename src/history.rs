@@ -0,0 +1,191 @@
+// Copyright 2019, Sjors van Gelderen
+
+use crate::animation::Animation;
+use crate::chr;
+use crate::metasprite::Metasprite;
+use crate::oam::Sprite;
+use crate::pattern_table::PatternTable;
+use crate::project::Project;
+use crate::samples::Samples;
+
+// Every edit stores both values so it can be applied in either direction
+pub enum Edit {
+    // Pixel coordinates with the color before and after, in the order they were changed
+    Pixels(Vec<(usize, usize, u8, u8)>),
+    Tile { nametable: usize, x: usize, y: usize, from: u8, to: u8 },
+    Attribute { nametable: usize, x: usize, y: usize, from: u8, to: u8 },
+    Sample { which: usize, from: u8, to: u8 },
+    Sprite { which: usize, from: Sprite, to: Sprite },
+    Metasprite { which: usize, from: Metasprite, to: Metasprite },
+    Animation { which: usize, from: Animation, to: Animation },
+    // Pushed onto the end when applied forward and popped off again when applied backward
+    AddMetasprite(Metasprite),
+    AddAnimation(Animation),
+    Batch(Vec<Edit>),
+}
+
+impl Edit {
+    fn is_empty(&self) -> bool {
+        match self {
+            Edit::Pixels(pixels) => pixels.is_empty(),
+            Edit::Batch(edits) => edits.iter().all(|x| x.is_empty()),
+            _ => false,
+        }
+    }
+
+    fn apply(&self, forward: bool, pattern_table: &mut PatternTable, samples: &mut Samples, project: &mut Project) {
+        let pick = |from: u8, to: u8| if forward { to } else { from };
+
+        match self {
+            Edit::Pixels(pixels) => {
+                let ordered: Vec<&(usize, usize, u8, u8)> = if forward {
+                    pixels.iter().collect()
+                }
+                else {
+                    pixels.iter().rev().collect()
+                };
+
+                for &(x, y, from, to) in ordered {
+                    chr::set_pixel(&mut pattern_table.pixels, &mut pattern_table.bytes, x, y, pick(from, to));
+                }
+            },
+            Edit::Tile { nametable, x, y, from, to } => {
+                project.nametables[*nametable].set_tile(*x, *y, pick(*from, *to));
+            },
+            Edit::Attribute { nametable, x, y, from, to } => {
+                project.nametables[*nametable].attribute_table.set_sub_palette(*x, *y, pick(*from, *to));
+            },
+            Edit::Sample { which, from, to } => {
+                samples.color_indices[*which] = pick(*from, *to);
+            },
+            Edit::Sprite { which, from, to } => {
+                project.oam.sprites[*which] = if forward { *to } else { *from };
+            },
+            Edit::Metasprite { which, from, to } => {
+                project.metasprites[*which] = if forward { to.clone() } else { from.clone() };
+            },
+            Edit::Animation { which, from, to } => {
+                project.animations[*which] = if forward { to.clone() } else { from.clone() };
+            },
+            Edit::AddMetasprite(metasprite) => {
+                if forward {
+                    project.metasprites.push(metasprite.clone());
+                }
+                else {
+                    project.metasprites.pop();
+                }
+            },
+            Edit::AddAnimation(animation) => {
+                if forward {
+                    project.animations.push(animation.clone());
+                }
+                else {
+                    project.animations.pop();
+                }
+            },
+            Edit::Batch(edits) => {
+                if forward {
+                    for edit in edits {
+                        edit.apply(forward, pattern_table, samples, project);
+                    }
+                }
+                else {
+                    for edit in edits.iter().rev() {
+                        edit.apply(forward, pattern_table, samples, project);
+                    }
+                }
+            },
+        }
+    }
+}
+
+// Edits recorded between begin and end, such as a single pencil stroke, are undone as one step
+pub struct History {
+    undo_stack: Vec<Edit>,
+    redo_stack: Vec<Edit>,
+    group: Option<Vec<Edit>>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            undo_stack: vec![],
+            redo_stack: vec![],
+            group: None,
+        }
+    }
+
+    pub fn begin(&mut self) {
+        self.end();
+        self.group = Some(vec![]);
+    }
+
+    pub fn end(&mut self) {
+        if let Some(mut edits) = self.group.take() {
+            let edit = if edits.len() == 1 {
+                edits.remove(0)
+            }
+            else {
+                Edit::Batch(edits)
+            };
+
+            self.push(edit);
+        }
+    }
+
+    pub fn record(&mut self, edit: Edit) {
+        if edit.is_empty() {
+            return;
+        }
+
+        match self.group {
+            Some(ref mut edits) => {
+                // Consecutive pixel edits are merged into one
+                if let (Some(Edit::Pixels(previous)), Edit::Pixels(pixels)) = (edits.last_mut(), &edit) {
+                    previous.extend(pixels.iter().cloned());
+                    return;
+                }
+
+                edits.push(edit);
+            },
+            None => self.push(edit),
+        }
+    }
+
+    // Returns whether anything was undone
+    pub fn undo(&mut self, pattern_table: &mut PatternTable, samples: &mut Samples, project: &mut Project) -> bool {
+        self.end();
+
+        match self.undo_stack.pop() {
+            Some(edit) => {
+                edit.apply(false, pattern_table, samples, project);
+                self.redo_stack.push(edit);
+
+                true
+            },
+            None => false
+        }
+    }
+
+    // Returns whether anything was redone
+    pub fn redo(&mut self, pattern_table: &mut PatternTable, samples: &mut Samples, project: &mut Project) -> bool {
+        self.end();
+
+        match self.redo_stack.pop() {
+            Some(edit) => {
+                edit.apply(true, pattern_table, samples, project);
+                self.undo_stack.push(edit);
+
+                true
+            },
+            None => false
+        }
+    }
+
+    fn push(&mut self, edit: Edit) {
+        if !edit.is_empty() {
+            self.undo_stack.push(edit);
+            self.redo_stack.clear();
+        }
+    }
+}
@@ -0,0 +1,4 @@
+// Copyright 2019, Sjors van Gelderen
+
+// Character data operations, such as the flood fill, for scripts that work without the editor
+pub mod chr;
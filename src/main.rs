@@ -1,27 +1,81 @@
 // Copyright 2019, Sjors van Gelderen
 
 extern crate cgmath;
+extern crate gif;
 extern crate image;
+extern crate nes_tool;
 extern crate vulkano;
 extern crate vulkano_shaders;
 extern crate vulkano_win;
 extern crate winit;
 
+mod animation;
+mod assembly;
 mod attribute_table;
+mod clipboard;
+mod color;
+mod fade;
+mod history;
 mod media;
+mod metasprite;
 mod mode;
 mod nametable;
+mod oam;
 mod palette;
 mod pattern_table;
+mod preview;
+mod project;
+mod render;
 mod samples;
+mod sprite_sheet;
 mod surface;
 mod system;
 mod tool;
 mod vertex;
 
+use nes_tool::chr;
+
+use crate::animation::{
+    Animation,
+    Cel,
+    Frame,
+};
+use crate::assembly::Source;
+use crate::chr::{
+    FillMode,
+    Transform,
+};
+use crate::clipboard::{
+    Area,
+    Clipboard,
+    Selection,
+};
+use crate::color::{
+    ColorMatcher,
+    Metric,
+};
+use crate::fade::FadeTarget;
+use crate::history::{
+    Edit,
+    History,
+};
+use crate::metasprite::{
+    BoxKind,
+    Hitbox,
+    Metasprite,
+};
+use crate::mode::Mode;
+use crate::oam::{
+    Oam,
+    Sprite,
+};
 use crate::palette::Palette;
 use crate::pattern_table::PatternTable;
+use crate::preview::Preview;
+use crate::project::Project;
 use crate::samples::Samples;
+use crate::sprite_sheet::SpriteSheet;
+use crate::tool::Tool;
 
 use crate::system::{
     Mouse,
@@ -35,8 +89,10 @@ use cgmath::{
 };
 
 use std::{
+    env,
     path::Path,
     sync::Arc,
+    time::Instant,
 };
 
 use vulkano::{
@@ -44,7 +100,10 @@ use vulkano::{
         AutoCommandBufferBuilder,
         DynamicState,
     },
-    device::Device,
+    device::{
+        Device,
+        Queue,
+    },
     sampler::{
         Filter,
         MipmapMode,
@@ -115,11 +174,17 @@ fn main() {
         device.clone(), queue.clone(), render_pass.clone(), sampler.clone()
     ).set_position(Vector3::new(-80.0, -80.0, 0.0));
 
-    let samples = Samples::new(
-        device.clone(), queue.clone(), render_pass.clone(), sampler.clone()
+    // A master palette in .gpl or .act format may be supplied as the first argument
+    let mut palette = match env::args().nth(1) {
+        Some(path) => palette.load_from_file(Path::new(&path), queue.clone(), sampler.clone()),
+        None => palette
+    };
+
+    let mut samples = Samples::new(
+        device.clone(), queue.clone(), render_pass.clone(), sampler.clone(), &palette.colors
     ).set_position(Vector3::new(80.0, -80.0, 0.0));
     
-    let pattern_table = PatternTable::new(
+    let mut pattern_table = PatternTable::new(
         device.clone(), queue.clone(), render_pass.clone(), sampler.clone()
     ).load_from_file(Path::new("mario.chr"), queue.clone(), sampler.clone());
 
@@ -135,11 +200,79 @@ fn main() {
 
     let mut recreate_swapchain = false;
 
-    let mut previous_frame_end = Box::new(
-        pattern_table.tex_future
-            .join(palette.tex_future)
-            .join(samples.tex_future)
-    ) as Box<GpuFuture>;
+    let mut fade_steps = 4;
+    let mut metric = Metric::Ciede2000;
+    let mut selected_color: u8 = 0x0F;
+    let mut tool = Tool::Pencil;
+    let mut tool_color: u8 = 1;
+    let mut mode = Mode::Nametable;
+    let mut selected_tile: u8 = 0;
+    let mut selected_sub_palette: u8 = 0;
+    let mut selected_sprite_palette: u8 = 0;
+    let mut selected_sprite: Option<usize> = None;
+
+    // Sprite being dragged, with its state before the drag and where it was grabbed
+    let mut moving_sprite: Option<(usize, Sprite, (i32, i32))> = None;
+
+    // Metasprites are captured from and loaded into OAM relative to this point of the sprite view
+    let mut metasprite_origin: (i32, i32) = (128, 120);
+    let mut selected_metasprite: Option<usize> = None;
+
+    // Annotations of the selected metasprite are placed at the mouse, boxes take two corners
+    let mut selected_anchor: usize = 0;
+    let mut box_corner: Option<(i32, i32)> = None;
+
+    // Leaves out the sprites the PPU would drop from full scanlines
+    let mut limit_sprites = true;
+
+    // Playback keeps the moment it started and the frame that is shown
+    let mut selected_animation: Option<usize> = None;
+    let mut playing: Option<(Instant, Option<usize>)> = None;
+
+    // The nametable view can show the sprites over the background as the whole scene
+    let mut show_sprites = false;
+
+    // What the nametable view last showed of the sprites and the sprite 0 hit
+    let mut shown_scene: Option<(Vec<u8>, bool, usize, bool)> = None;
+    let mut shown_hit: Option<(usize, usize)> = None;
+
+    // Edits mark the views, which are refreshed once per frame
+    let mut nametable_dirty = true;
+    let mut sprite_view_dirty = true;
+
+    // Tiles of the paste that follows the mouse over the nametable view
+    let mut nametable_overlay: Vec<(usize, usize, u8)> = vec![];
+
+    let mut project = Project::new();
+    let mut history = History::new();
+
+    let mut selection: Option<Selection> = None;
+    let mut selecting = false;
+    let mut clipboard: Option<Clipboard> = None;
+    let mut pasting = false;
+    let mut show_unused = false;
+
+    // Start and most recent pixel of the stroke in progress
+    let mut stroke: Option<((i32, i32), (i32, i32))> = None;
+
+    let mut fade_preview = Preview::new(
+        device.clone(), queue.clone(), render_pass.clone(), sampler.clone(),
+        Vector2::new(26, 1), Vector2::new(52.0, 36.0)
+    ).set_position(Vector3::new(140.0, 0.0, 0.0));
+
+    update_fade_preview(&mut fade_preview, &samples, &palette, fade_steps, queue.clone(), sampler.clone());
+
+    let mut nametable_view = Preview::new(
+        device.clone(), queue.clone(), render_pass.clone(), sampler.clone(),
+        Vector2::new(256, 240), Vector2::new(64.0, 60.0)
+    ).set_position(Vector3::new(-140.0, 0.0, 0.0));
+
+    let mut sprite_view = Preview::new(
+        device.clone(), queue.clone(), render_pass.clone(), sampler.clone(),
+        Vector2::new(256, 240), Vector2::new(64.0, 60.0)
+    ).set_position(Vector3::new(140.0, 65.0, 0.0));
+
+    let mut previous_frame_end = Box::new(now(device.clone())) as Box<GpuFuture>;
 
     let mut view = View::new(Vector2::new(1600, 900));
     let mut mouse = Mouse::new();
@@ -149,6 +282,103 @@ fn main() {
     loop {
         previous_frame_end.cleanup_finished();
 
+        // Usage changes with almost any edit, the texture is only refreshed when the result differs
+        let marked = if show_unused {
+            (0..2).flat_map(|page| {
+                project.get_unused_tiles(page).into_iter().map(move |x| page * 256 + x as usize)
+            }).collect()
+        }
+        else {
+            vec![]
+        };
+
+        pattern_table.set_marked(marked, queue.clone(), sampler.clone());
+
+        // Sprite edits are not tracked by the nametable view, it is refreshed when the sprites or the hit change
+        let scene = if show_sprites {
+            Some((project.oam.to_bytes().to_vec(), project.tall_sprites, project.sprite_page, limit_sprites))
+        }
+        else {
+            None
+        };
+
+        let hit = project.get_sprite_zero_hit(&pattern_table.pixels);
+
+        if hit != shown_hit || scene != shown_scene {
+            shown_hit = hit;
+            shown_scene = scene;
+
+            nametable_dirty = true;
+        }
+
+        if nametable_dirty {
+            let overlay: &[(usize, usize, u8)] = if pasting { &nametable_overlay } else { &[] };
+
+            update_nametable_view(
+                &mut nametable_view, &project, overlay, &selection, show_sprites, limit_sprites,
+                &pattern_table, &samples, &palette, queue.clone(), sampler.clone()
+            );
+
+            nametable_dirty = false;
+        }
+
+        // During playback the shown frame is rendered again instead
+        if sprite_view_dirty {
+            match playing {
+                Some((start, _)) => playing = Some((start, None)),
+                None => update_sprite_view(
+                    &mut sprite_view, &project, selected_sprite, selected_metasprite, metasprite_origin, limit_sprites,
+                    &pattern_table, &samples, &palette, queue.clone(), sampler.clone()
+                ),
+            }
+
+            sprite_view_dirty = false;
+        }
+
+        // The sprite view only changes when playback reaches another frame
+        if let Some((start, shown)) = playing {
+            let elapsed = start.elapsed();
+            let seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1_000_000_000.0;
+            let tick = (seconds * animation::NTSC_FRAME_RATE) as usize;
+            let frame = selected_animation.and_then(|x| project.animations[x].get_frame_at(tick));
+
+            if frame != shown {
+                match (selected_animation, frame) {
+                    (Some(which), Some(frame)) => {
+                        let image_data = render_animation_frame(
+                            &project, &project.animations[which], frame, metasprite_origin,
+                            &pattern_table, &samples, &palette
+                        );
+
+                        sprite_view.update_image(Vector2::new(256, 240), &image_data, queue.clone(), sampler.clone());
+                        playing = Some((start, Some(frame)));
+                    },
+                    _ => {
+                        playing = None;
+
+                        update_sprite_view(
+                            &mut sprite_view, &project, selected_sprite, selected_metasprite, metasprite_origin, limit_sprites,
+                            &pattern_table, &samples, &palette, queue.clone(), sampler.clone()
+                        );
+                    }
+                }
+            }
+        }
+
+        // Join any texture uploads that were started since the previous frame
+        let tex_futures = vec![
+            pattern_table.tex_future.take(),
+            palette.tex_future.take(),
+            samples.tex_future.take(),
+            fade_preview.tex_future.take(),
+            nametable_view.tex_future.take(),
+            sprite_view.tex_future.take(),
+        ];
+
+        for tex_future in tex_futures.into_iter().flatten() {
+            previous_frame_end = Box::new(previous_frame_end.join(tex_future)) as Box<GpuFuture>;
+        }
+
         if recreate_swapchain {
             let dimensions = if let Some(dimensions) = window.get_inner_size() {
                 let dimensions: (u32, u32) = dimensions.to_physical(window.get_hidpi_factor()).into();
@@ -183,7 +413,7 @@ fn main() {
                 pattern_table.surface.position.x,
                 pattern_table.surface.position.y
             ),
-            pattern_table.surface.dimensions
+            pattern_table.surface.dimensions * view.zoom
         );
 
         let mvp = view.mvp(Matrix4::from_translation(pattern_table.surface.position) * Matrix4::from_scale(view.zoom));
@@ -237,6 +467,57 @@ fn main() {
             mouse: [ samples_mouse.x, samples_mouse.y ],
         };
 
+        let mvp = view.mvp(Matrix4::from_translation(fade_preview.surface.position));
+        let fade_preview_push_constants = preview::vs::ty::UBO {
+            mvp: [
+                [ mvp.x.x, mvp.x.y, mvp.x.z, mvp.x.w ],
+                [ mvp.y.x, mvp.y.y, mvp.y.z, mvp.y.w ],
+                [ mvp.z.x, mvp.z.y, mvp.z.z, mvp.z.w ],
+                [ mvp.w.x, mvp.w.y, mvp.w.z, mvp.w.w ],
+            ],
+            mouse: [ -1.0, -1.0 ],
+        };
+
+        let nametable_view_mouse = get_mouse_position_on_surface(
+            mouse.position,
+            Vector2::new(
+                nametable_view.surface.position.x,
+                nametable_view.surface.position.y
+            ),
+            nametable_view.surface.dimensions
+        );
+
+        let mvp = view.mvp(Matrix4::from_translation(nametable_view.surface.position));
+        let nametable_view_push_constants = preview::vs::ty::UBO {
+            mvp: [
+                [ mvp.x.x, mvp.x.y, mvp.x.z, mvp.x.w ],
+                [ mvp.y.x, mvp.y.y, mvp.y.z, mvp.y.w ],
+                [ mvp.z.x, mvp.z.y, mvp.z.z, mvp.z.w ],
+                [ mvp.w.x, mvp.w.y, mvp.w.z, mvp.w.w ],
+            ],
+            mouse: [ nametable_view_mouse.x, nametable_view_mouse.y ],
+        };
+
+        let sprite_view_mouse = get_mouse_position_on_surface(
+            mouse.position,
+            Vector2::new(
+                sprite_view.surface.position.x,
+                sprite_view.surface.position.y
+            ),
+            sprite_view.surface.dimensions
+        );
+
+        let mvp = view.mvp(Matrix4::from_translation(sprite_view.surface.position));
+        let sprite_view_push_constants = preview::vs::ty::UBO {
+            mvp: [
+                [ mvp.x.x, mvp.x.y, mvp.x.z, mvp.x.w ],
+                [ mvp.y.x, mvp.y.y, mvp.y.z, mvp.y.w ],
+                [ mvp.z.x, mvp.z.y, mvp.z.z, mvp.z.w ],
+                [ mvp.w.x, mvp.w.y, mvp.w.z, mvp.w.w ],
+            ],
+            mouse: [ sprite_view_mouse.x, sprite_view_mouse.y ],
+        };
+
         let (image_number, acquire_future) =
             match acquire_next_image(swapchain.clone(), None) {
                 Ok(r) => r,
@@ -283,6 +564,30 @@ fn main() {
             samples.descriptor_set.clone(),
             samples_push_constants
         ).unwrap()
+        .draw_indexed(
+            fade_preview.pipeline.clone(),
+            &dynamic_state,
+            fade_preview.surface.vertex_buffer.clone(),
+            fade_preview.surface.index_buffer.clone(),
+            fade_preview.descriptor_set.clone(),
+            fade_preview_push_constants
+        ).unwrap()
+        .draw_indexed(
+            nametable_view.pipeline.clone(),
+            &dynamic_state,
+            nametable_view.surface.vertex_buffer.clone(),
+            nametable_view.surface.index_buffer.clone(),
+            nametable_view.descriptor_set.clone(),
+            nametable_view_push_constants
+        ).unwrap()
+        .draw_indexed(
+            sprite_view.pipeline.clone(),
+            &dynamic_state,
+            sprite_view.surface.vertex_buffer.clone(),
+            sprite_view.surface.index_buffer.clone(),
+            sprite_view.descriptor_set.clone(),
+            sprite_view_push_constants
+        ).unwrap()
         .end_render_pass().unwrap()
         .build().unwrap();
 
@@ -331,21 +636,362 @@ fn main() {
                         mp.x / wd.x * pd.x * aspect - pd.x * aspect / 2.0,
                         mp.y / wd.y * pd.y - pd.y / 2.0,
                     );
+
+                    if selecting {
+                        if let Some(selection) = selection.as_mut() {
+                            let tile = match selection.area {
+                                Area::PatternTable => get_pattern_table_pixel(&mouse, &pattern_table, &view)
+                                    .map(|(x, y)| (x as usize / 8, y as usize / 8)),
+                                Area::Nametable => get_nametable_tile(&mouse, &nametable_view),
+                            };
+
+                            if let Some(tile) = tile {
+                                selection.to = tile;
+                            }
+                        }
+
+                        pattern_table.update_texture(&get_pattern_table_overlay(&selection), queue.clone(), sampler.clone());
+
+                        nametable_dirty = true;
+                    }
+                    else if let (true, Some(clipboard)) = (pasting, &clipboard) {
+                        // The paste follows the mouse in whichever view it is over
+                        let mut pattern_table_overlay = get_pattern_table_overlay(&selection);
+                        nametable_overlay = vec![];
+
+                        if let Some((x, y)) = get_pattern_table_pixel(&mouse, &pattern_table, &view) {
+                            if !pattern_table.tall {
+                                pattern_table_overlay.extend(clipboard.get_sheet_pixels(x as usize / 8, y as usize / 8));
+                            }
+                        }
+                        else if let Some((x, y)) = get_nametable_tile(&mouse, &nametable_view) {
+                            nametable_overlay = clipboard.get_nametable_tiles(x, y);
+                        }
+
+                        pattern_table.update_texture(&pattern_table_overlay, queue.clone(), sampler.clone());
+
+                        nametable_dirty = true;
+                    }
+
+                    if let (Some((which, _, (dx, dy))), Some((x, y))) = (moving_sprite, get_sprite_view_pixel(&mouse, &sprite_view)) {
+                        let sprite = &mut project.oam.sprites[which];
+
+                        sprite.x = (x as i32 - dx).max(0).min(255) as u8;
+                        sprite.y = ((y as i32 - dy).max(1).min(239) - 1) as u8;
+
+                        sprite_view_dirty = true;
+                    }
+
+                    if let (Some((start, last)), Some(pixel)) = (stroke, get_pattern_table_pixel(&mouse, &pattern_table, &view)) {
+                        if tool.is_freehand() {
+                            history.record(Edit::Pixels(pattern_table.draw(&tool.get_points(last, pixel), tool_color)));
+                            pattern_table.update_texture(&get_pattern_table_overlay(&selection), queue.clone(), sampler.clone());
+                        }
+                        else if let Tool::Fill(_) = tool {
+                            // Fills apply where the mouse is released
+                        }
+                        else {
+                            // Shapes are previewed until the mouse is released
+                            let mut overlay = get_pattern_table_overlay(&selection);
+
+                            overlay.extend(tool.get_points(start, pixel).iter()
+                                .filter(|&&(x, y)| chr::is_on_sheet(x, y))
+                                .map(|&(x, y)| (x as usize, y as usize, tool_color))
+                            );
+
+                            pattern_table.update_texture(&overlay, queue.clone(), sampler.clone());
+                        }
+
+                        stroke = Some((start, pixel));
+                    }
                 },
                 Event::WindowEvent {
-                    event: WindowEvent::MouseInput { state, button, .. },
+                    event: WindowEvent::MouseInput { state, button, modifiers, .. },
                     ..
                 } => {
                     match button {
                         MouseButton::Left => {
-                            // mouse.left_down = state == ElementState::Pressed;
+                            if state == ElementState::Released {
+                                selecting = false;
+
+                                if let Some((which, from, _)) = moving_sprite.take() {
+                                    if project.oam.sprites[which] != from {
+                                        history.record(Edit::Sprite { which, from, to: project.oam.sprites[which] });
+                                    }
+                                }
+
+                                if let Some((start, last)) = stroke.take() {
+                                    let changes = match tool {
+                                        Tool::Fill(mode) => pattern_table.fill(
+                                            last.0 as usize, last.1 as usize, tool_color, mode
+                                        ),
+                                        Tool::Reorder => {
+                                            let from = chr::get_tile_index(start.0 as usize, start.1 as usize);
+                                            let to = chr::get_tile_index(last.0 as usize, last.1 as usize);
+
+                                            let mapping = if modifiers.shift {
+                                                chr::get_swap_mapping(from % 256, to % 256)
+                                            }
+                                            else {
+                                                chr::get_move_mapping(from % 256, to % 256)
+                                            };
+
+                                            let split = project.get_split_pairs(from / 256, &mapping);
+
+                                            if from / 256 != to / 256 {
+                                                println!("Tiles can only be reordered within a page");
+                                                vec![]
+                                            }
+                                            // 8x16 sprites would keep showing the tiles that took the place of their pair
+                                            else if !split.is_empty() {
+                                                let tiles: Vec<String> = split.iter().map(|x| format!("${:02X}", x)).collect();
+                                                println!("Reordering would split the 8x16 pairs of tiles {}", tiles.join(" "));
+                                                vec![]
+                                            }
+                                            else {
+                                                for edit in project.remap_tiles(from / 256, &mapping) {
+                                                    history.record(edit);
+                                                }
+
+                                                pattern_table.set_pixels(&chr::get_remapped(&pattern_table.pixels, from / 256, &mapping))
+                                            }
+                                        },
+                                        _ if !tool.is_freehand() => pattern_table.draw(
+                                            &tool.get_points(start, last), tool_color
+                                        ),
+                                        _ => vec![]
+                                    };
+
+                                    history.record(Edit::Pixels(changes));
+                                    history.end();
+
+                                    pattern_table.update_texture(&get_pattern_table_overlay(&selection), queue.clone(), sampler.clone());
+
+                                    nametable_dirty = true;
+                                    sprite_view_dirty = true;
+                                }
+                            }
 
                             if state == ElementState::Pressed {
                                 mouse.drag_start = mouse.position;
+
+                                if pasting {
+                                    if let Some(clipboard) = &clipboard {
+                                        if let Some((x, y)) = get_pattern_table_pixel(&mouse, &pattern_table, &view) {
+                                            if pattern_table.tall {
+                                                println!("Blocks can only be pasted in the regular arrangement");
+                                            }
+                                            else {
+                                                history.record(Edit::Pixels(
+                                                    pattern_table.set_pixels(&clipboard.get_sheet_pixels(x as usize / 8, y as usize / 8))
+                                                ));
+                                            }
+                                        }
+                                        else if let Some((x, y)) = get_nametable_tile(&mouse, &nametable_view) {
+                                            history.begin();
+
+                                            for (x, y, tile) in clipboard.get_nametable_tiles(x, y) {
+                                                let from = project.nametables[0].set_tile(x, y, tile);
+                                                history.record(Edit::Tile { nametable: 0, x, y, from, to: tile });
+                                            }
+
+                                            history.end();
+                                        }
+                                    }
+
+                                    pasting = false;
+
+                                    pattern_table.update_texture(&get_pattern_table_overlay(&selection), queue.clone(), sampler.clone());
+
+                                    nametable_dirty = true;
+                                    sprite_view_dirty = true;
+                                }
+                                else if let Some(cell) = get_cell_on_surface(palette_mouse, 16, 4) {
+                                    selected_color = cell as u8;
+                                }
+                                else if let Some(cell) = get_cell_on_surface(samples_mouse, 13, 2) {
+                                    let from = samples.color_indices[cell];
+                                    let result = samples.set_color_index(
+                                        cell,
+                                        selected_color,
+                                        palette.block_0d,
+                                        &palette.colors,
+                                        queue.clone(),
+                                        sampler.clone()
+                                    );
+
+                                    match result {
+                                        Ok(Some(warning)) => println!("Assigned ${:02X}: {:?}", selected_color, warning),
+                                        Ok(None) => (),
+                                        Err(warning) => println!("Blocked ${:02X}: {:?}", selected_color, warning)
+                                    }
+
+                                    if result.is_ok() {
+                                        history.record(Edit::Sample { which: cell, from, to: selected_color });
+                                    }
+
+                                    update_fade_preview(
+                                        &mut fade_preview, &samples, &palette, fade_steps, queue.clone(), sampler.clone()
+                                    );
+
+                                    nametable_dirty = true;
+                                    sprite_view_dirty = true;
+                                }
+                                else if let Some((x, y)) = get_sprite_view_pixel(&mouse, &sprite_view) {
+                                    // Clicking a sprite picks it up, clicking elsewhere places a new one
+                                    match project.oam.get_sprite_at(x, y, project.tall_sprites) {
+                                        Some(which) => {
+                                            let sprite = project.oam.sprites[which];
+                                            let offset = (x as i32 - sprite.x as i32, y as i32 - sprite.get_top() as i32);
+
+                                            moving_sprite = Some((which, sprite, offset));
+                                            selected_sprite = Some(which);
+                                        },
+                                        None => match project.oam.get_free_sprite() {
+                                            Some(which) => {
+                                                // Tall sprites take the top tile of the pair and the page from bit 0
+                                                let tile = if project.tall_sprites {
+                                                    (selected_tile & 0xFE) | project.sprite_page as u8
+                                                }
+                                                else {
+                                                    selected_tile
+                                                };
+
+                                                let sprite = Sprite {
+                                                    y: (y.max(1) - 1) as u8,
+                                                    tile,
+                                                    attributes: selected_sprite_palette,
+                                                    x: x as u8,
+                                                };
+
+                                                history.record(Edit::Sprite { which, from: project.oam.sprites[which], to: sprite });
+                                                project.oam.sprites[which] = sprite;
+                                                selected_sprite = Some(which);
+                                            },
+                                            None => println!("All 64 sprites are in use")
+                                        }
+                                    }
+
+                                    sprite_view_dirty = true;
+                                }
+                                else if let Some((x, y)) = get_nametable_tile(&mouse, &nametable_view) {
+                                    let nametable = &mut project.nametables[0];
+
+                                    if tool == Tool::Select {
+                                        selection = Some(Selection { area: Area::Nametable, from: (x, y), to: (x, y) });
+                                        selecting = true;
+
+                                        pattern_table.update_texture(&get_pattern_table_overlay(&selection), queue.clone(), sampler.clone());
+                                    }
+                                    else {
+                                        match mode {
+                                            Mode::Character => selected_tile = nametable.get_tile(x, y),
+                                            Mode::Nametable => {
+                                                let from = nametable.set_tile(x, y, selected_tile);
+                                                history.record(Edit::Tile { nametable: 0, x, y, from, to: selected_tile });
+                                            },
+                                            Mode::Attribute => {
+                                                let from = nametable.attribute_table.set_sub_palette(x, y, selected_sub_palette);
+                                                history.record(Edit::Attribute { nametable: 0, x, y, from, to: selected_sub_palette });
+                                            },
+                                        }
+                                    }
+
+                                    nametable_dirty = true;
+                                    sprite_view_dirty = true;
+                                }
+                                // A block in the 8x16 arrangement is not a rectangle on the sheet
+                                else if tool == Tool::Select && pattern_table.tall && get_pattern_table_pixel(&mouse, &pattern_table, &view).is_some() {
+                                    println!("Blocks can only be selected in the regular arrangement");
+                                }
+                                else if let (Tool::Select, Some((x, y))) = (tool, get_pattern_table_pixel(&mouse, &pattern_table, &view)) {
+                                    let tile = (x as usize / 8, y as usize / 8);
+
+                                    selection = Some(Selection { area: Area::PatternTable, from: tile, to: tile });
+                                    selecting = true;
+
+                                    pattern_table.update_texture(&get_pattern_table_overlay(&selection), queue.clone(), sampler.clone());
+
+                                    nametable_dirty = true;
+                                }
+                                else if let Some(pixel) = get_pattern_table_pixel(&mouse, &pattern_table, &view) {
+                                    history.begin();
+
+                                    if tool.is_freehand() {
+                                        history.record(Edit::Pixels(pattern_table.draw(&tool.get_points(pixel, pixel), tool_color)));
+                                        pattern_table.update_texture(&get_pattern_table_overlay(&selection), queue.clone(), sampler.clone());
+                                    }
+
+                                    stroke = Some((pixel, pixel));
+                                }
+                            }
+                        },
+                        MouseButton::Middle => {
+                            if let (ElementState::Pressed, Some((x, y))) = (state, get_sprite_view_pixel(&mouse, &sprite_view)) {
+                                metasprite_origin = (x as i32, y as i32);
+
+                                sprite_view_dirty = true;
                             }
                         },
                         MouseButton::Right => {
-                            // mouse.left_down = state == ElementState::Pressed;
+                            if state == ElementState::Pressed {
+                                if pasting {
+                                    pasting = false;
+
+                                    pattern_table.update_texture(&get_pattern_table_overlay(&selection), queue.clone(), sampler.clone());
+
+                                    nametable_dirty = true;
+                                }
+                                // Picking a tile also selects its page, for sprites while shift is held
+                                else if let Some((x, y)) = get_pattern_table_pixel(&mouse, &pattern_table, &view) {
+                                    let tile = chr::get_tile_index(x as usize, y as usize);
+
+                                    selected_tile = (tile % 256) as u8;
+
+                                    if modifiers.shift {
+                                        project.sprite_page = tile / 256;
+                                    }
+                                    else {
+                                        project.background_page = tile / 256;
+                                    }
+
+                                    nametable_dirty = true;
+                                    sprite_view_dirty = true;
+                                }
+                                else if let Some(cell) = get_cell_on_surface(samples_mouse, 13, 2) {
+                                    let sub_palette = ((cell % 13).max(1) - 1) as u8 / 3;
+
+                                    // The second row holds the sprite sub-palettes, which also go to the selected sprite
+                                    if cell < 13 {
+                                        selected_sub_palette = sub_palette;
+                                    }
+                                    else {
+                                        selected_sprite_palette = sub_palette;
+
+                                        if let Some(which) = selected_sprite {
+                                            let from = project.oam.sprites[which];
+                                            let to = from.set_sub_palette(sub_palette);
+
+                                            history.record(Edit::Sprite { which, from, to });
+                                            project.oam.sprites[which] = to;
+
+                                            sprite_view_dirty = true;
+                                        }
+                                    }
+                                }
+                                else if let Some((x, y)) = get_sprite_view_pixel(&mouse, &sprite_view) {
+                                    selected_sprite = project.oam.get_sprite_at(x, y, project.tall_sprites);
+
+                                    if let Some(which) = selected_sprite {
+                                        let sprite = project.oam.sprites[which];
+
+                                        selected_tile = sprite.tile;
+                                        selected_sprite_palette = sprite.get_sub_palette();
+                                    }
+
+                                    sprite_view_dirty = true;
+                                }
+                            }
                         },
                         _ => ()
                     }
@@ -366,6 +1012,7 @@ fn main() {
                         input: KeyboardInput {
                             virtual_keycode: Some(code),
                             state,
+                            modifiers,
                             ..
                         },
                         ..
@@ -378,6 +1025,578 @@ fn main() {
                     else if code == VirtualKeyCode::Space {
                         mouse.dragging = state == ElementState::Pressed;
                     }
+                    else if state == ElementState::Pressed && modifiers.ctrl {
+                        let changed = match code {
+                            VirtualKeyCode::Z if modifiers.shift => history.redo(&mut pattern_table, &mut samples, &mut project),
+                            VirtualKeyCode::Z => history.undo(&mut pattern_table, &mut samples, &mut project),
+                            VirtualKeyCode::Y => history.redo(&mut pattern_table, &mut samples, &mut project),
+                            VirtualKeyCode::K => swap_colors(
+                                tool_color, modifiers.shift, Some((selected_sub_palette, selected_sprite_palette)), &selection, &mouse, &view,
+                                &mut pattern_table, &mut samples, &mut history
+                            ),
+                            VirtualKeyCode::C | VirtualKeyCode::X => match selection {
+                                Some(selection) => {
+                                    clipboard = Some(match selection.area {
+                                        Area::PatternTable => Clipboard::from_pattern_table(&pattern_table.pixels, &selection),
+                                        Area::Nametable => Clipboard::from_nametable(
+                                            &project.nametables[0], &pattern_table.pixels, project.background_page, &selection
+                                        ),
+                                    });
+
+                                    // Cutting clears the selection to color 0 or tile 0
+                                    if code == VirtualKeyCode::X {
+                                        let (x0, y0, width, height) = selection.get_bounds();
+
+                                        history.begin();
+
+                                        match selection.area {
+                                            Area::PatternTable => {
+                                                let points = tool::get_rectangle(
+                                                    ((x0 * 8) as i32, (y0 * 8) as i32),
+                                                    (((x0 + width) * 8 - 1) as i32, ((y0 + height) * 8 - 1) as i32),
+                                                    true
+                                                );
+
+                                                history.record(Edit::Pixels(pattern_table.draw(&points, 0)));
+                                            },
+                                            Area::Nametable => {
+                                                for y in y0..y0 + height {
+                                                    for x in x0..x0 + width {
+                                                        let from = project.nametables[0].set_tile(x, y, 0);
+                                                        history.record(Edit::Tile { nametable: 0, x, y, from, to: 0 });
+                                                    }
+                                                }
+                                            },
+                                        }
+
+                                        history.end();
+                                    }
+
+                                    code == VirtualKeyCode::X
+                                },
+                                None => false
+                            },
+                            VirtualKeyCode::V => {
+                                pasting = clipboard.is_some();
+                                false
+                            },
+                            VirtualKeyCode::E => {
+                                export_assembly(modifiers.shift, &pattern_table, &samples, &project);
+                                false
+                            },
+                            // Shows color 0 as a checkerboard on the banks that are used for sprites
+                            VirtualKeyCode::T => {
+                                let checkerboard = !pattern_table.checkerboard;
+                                let sprite_banks = pattern_table.sprite_banks;
+                                pattern_table.set_transparency(checkerboard, sprite_banks, queue.clone(), sampler.clone());
+
+                                true
+                            },
+                            // Switches the bank under the mouse between sprites and background
+                            VirtualKeyCode::B => match get_pattern_table_pixel(&mouse, &pattern_table, &view) {
+                                Some((x, _)) => {
+                                    let checkerboard = pattern_table.checkerboard;
+                                    let mut sprite_banks = pattern_table.sprite_banks;
+                                    sprite_banks[x as usize / 128] = !sprite_banks[x as usize / 128];
+                                    pattern_table.set_transparency(checkerboard, sprite_banks, queue.clone(), sampler.clone());
+
+                                    println!(
+                                        "Bank {} is used for {}",
+                                        x / 128, if sprite_banks[x as usize / 128] { "sprites" } else { "background" }
+                                    );
+
+                                    false
+                                },
+                                None => false
+                            },
+                            _ => false
+                        };
+
+                        if changed {
+                            let color_indices = samples.color_indices;
+
+                            // Undoing can remove the selected metasprite or animation
+                            selected_metasprite = selected_metasprite.filter(|x| *x < project.metasprites.len());
+                            selected_animation = selected_animation.filter(|x| *x < project.animations.len());
+
+                            pattern_table.update_texture(&get_pattern_table_overlay(&selection), queue.clone(), sampler.clone());
+                            samples.set_color_indices(color_indices, &palette.colors, queue.clone(), sampler.clone());
+
+                            update_fade_preview(
+                                &mut fade_preview, &samples, &palette, fade_steps, queue.clone(), sampler.clone()
+                            );
+
+                            nametable_dirty = true;
+                            sprite_view_dirty = true;
+                        }
+                    }
+                    else if state == ElementState::Pressed {
+                        match code {
+                            // Over the sprite view these keys flip, prioritize and move the selected sprite, as long as it is shown
+                            VirtualKeyCode::H | VirtualKeyCode::V | VirtualKeyCode::P | VirtualKeyCode::Delete
+                            | VirtualKeyCode::Left | VirtualKeyCode::Right | VirtualKeyCode::Up | VirtualKeyCode::Down
+                            if selected_sprite.map_or(false, |x| !project.oam.sprites[x].is_hidden())
+                            && get_sprite_view_pixel(&mouse, &sprite_view).is_some() => {
+                                let which = selected_sprite.unwrap();
+                                let from = project.oam.sprites[which];
+                                let to = match code {
+                                    VirtualKeyCode::H => from.toggle_attribute(oam::FLIP_HORIZONTAL),
+                                    VirtualKeyCode::V => from.toggle_attribute(oam::FLIP_VERTICAL),
+                                    VirtualKeyCode::P => from.toggle_attribute(oam::BEHIND_BACKGROUND),
+                                    VirtualKeyCode::Delete => Sprite::hidden(),
+                                    VirtualKeyCode::Left => Sprite { x: from.x.saturating_sub(1), ..from },
+                                    VirtualKeyCode::Right => Sprite { x: from.x.saturating_add(1), ..from },
+                                    VirtualKeyCode::Up => Sprite { y: from.y.max(1) - 1, ..from },
+                                    _ => Sprite { y: from.y.saturating_add(1).min(0xEE), ..from },
+                                };
+
+                                history.record(Edit::Sprite { which, from, to });
+                                project.oam.sprites[which] = to;
+
+                                // A deleted sprite cannot be clicked anymore, so it stops being selected
+                                if to.is_hidden() {
+                                    selected_sprite = None;
+                                }
+
+                                sprite_view_dirty = true;
+                            },
+                            // Over the sprite view these keys annotate the selected metasprite, relative to the origin
+                            VirtualKeyCode::Y | VirtualKeyCode::F | VirtualKeyCode::Back
+                            if selected_metasprite.is_some() && get_sprite_view_pixel(&mouse, &sprite_view).is_some() => {
+                                let which = selected_metasprite.unwrap();
+                                let (x, y) = get_sprite_view_pixel(&mouse, &sprite_view).unwrap();
+                                let (dx, dy) = (x as i32 - metasprite_origin.0, y as i32 - metasprite_origin.1);
+                                let from = project.metasprites[which].clone();
+                                let mut to = from.clone();
+
+                                match code {
+                                    // The first press marks a corner, the second adds the box, shift makes it a hurtbox
+                                    VirtualKeyCode::Y => match box_corner.take() {
+                                        Some(corner) => {
+                                            let kind = if modifiers.shift { BoxKind::Hurtbox } else { BoxKind::Hitbox };
+                                            to.hitboxes.push(Hitbox::from_corners(kind, corner, (dx, dy)));
+                                        },
+                                        None => box_corner = Some((dx, dy)),
+                                    },
+                                    VirtualKeyCode::F if modifiers.shift => {
+                                        selected_anchor = (selected_anchor + 1) % metasprite::ANCHORS.len();
+                                        println!("Placing the {} anchor", metasprite::ANCHORS[selected_anchor]);
+                                    },
+                                    VirtualKeyCode::F => {
+                                        to.anchors[selected_anchor] = Some((dx.max(-127).min(127) as i8, dy.max(-127).min(127) as i8));
+                                    },
+                                    // Removes the boxes under the mouse, or otherwise the anchors on it
+                                    _ => {
+                                        to.hitboxes.retain(|x| !x.contains(dx, dy));
+
+                                        if to.hitboxes.len() == from.hitboxes.len() {
+                                            for anchor in to.anchors.iter_mut() {
+                                                if *anchor == Some((dx as i8, dy as i8)) {
+                                                    *anchor = None;
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+
+                                if to != from {
+                                    history.record(Edit::Metasprite { which, from, to: to.clone() });
+                                    project.metasprites[which] = to;
+                                }
+
+                                sprite_view_dirty = true;
+                            },
+                            VirtualKeyCode::F11 if modifiers.shift => {
+                                match media::load_oam(Path::new("oam.bin")) {
+                                    Ok(bytes) => {
+                                        replace_oam(Oam::from_bytes(&bytes), &mut project, &mut history);
+                                        selected_sprite = None;
+
+                                        sprite_view_dirty = true;
+                                    },
+                                    Err(e) => println!("Failed to load OAM: {:?}", e)
+                                }
+                            },
+                            VirtualKeyCode::F11 => {
+                                if let Err(e) = media::save_oam(Path::new("oam.bin"), &project.oam.to_bytes()) {
+                                    println!("Failed to save OAM: {:?}", e);
+                                }
+                            },
+                            VirtualKeyCode::O if modifiers.shift => {
+                                let overflow = project.oam.get_overflow(project.tall_sprites);
+
+                                for (y, dropped) in overflow.iter() {
+                                    let dropped: Vec<String> = dropped.iter().map(|x| x.to_string()).collect();
+                                    println!("Scanline {} drops sprites {}", y, dropped.join(", "));
+                                }
+
+                                println!("{} scanlines over the limit of {}", overflow.len(), oam::SPRITES_PER_SCANLINE);
+                            },
+                            // The nametable view picks up the change on the next frame
+                            VirtualKeyCode::W => show_sprites = !show_sprites,
+                            // Pixels are output from dot 1 onwards, so the hit occurs one dot after its x coordinate
+                            VirtualKeyCode::X => {
+                                match project.get_sprite_zero_hit(&pattern_table.pixels) {
+                                    Some((x, y)) => println!("Sprite 0 hits at scanline {}, dot {}", y, x + 1),
+                                    None => println!("Sprite 0 never hits"),
+                                }
+                            },
+                            VirtualKeyCode::O => {
+                                limit_sprites = !limit_sprites;
+
+                                sprite_view_dirty = true;
+                            },
+                            // Blocks are selected, pasted and transformed in the regular arrangement, so a block selection is dropped
+                            VirtualKeyCode::F10 if modifiers.shift => {
+                                let tall = !pattern_table.tall;
+
+                                if tall && selection.map_or(false, |x| x.area == Area::PatternTable) {
+                                    selection = None;
+                                }
+
+                                pattern_table.set_tall(tall, queue.clone(), sampler.clone());
+                                pattern_table.update_texture(&get_pattern_table_overlay(&selection), queue.clone(), sampler.clone());
+                            },
+                            VirtualKeyCode::F10 => {
+                                project.tall_sprites = !project.tall_sprites;
+
+                                sprite_view_dirty = true;
+                            },
+                            VirtualKeyCode::F12 if modifiers.shift => {
+                                let mirrored: Vec<Metasprite> = project.metasprites.iter()
+                                    .map(|x| x.get_mirrored(true, false, project.tall_sprites))
+                                    .collect();
+
+                                let result = media::save_metasprites(Path::new("metasprites.bin"), &project.metasprites)
+                                    .and_then(|_| media::save_metasprites(Path::new("metasprites_mirrored.bin"), &mirrored))
+                                    .and_then(|_| media::save_hitboxes(Path::new("hitboxes.bin"), &project.metasprites))
+                                    .and_then(|_| media::save_hitboxes(Path::new("hitboxes_mirrored.bin"), &mirrored))
+                                    .and_then(|_| media::save_anchors(Path::new("anchors.bin"), &project.metasprites))
+                                    .and_then(|_| media::save_anchors(Path::new("anchors_mirrored.bin"), &mirrored));
+
+                                if let Err(e) = result {
+                                    println!("Failed to export metasprites: {:?}", e);
+                                }
+                            },
+                            // Stores the sprites as the loaded metasprite, or as a new one when none is loaded or alt is held
+                            VirtualKeyCode::F12 => {
+                                let metasprite = Metasprite::from_sprites(&project.oam.sprites, metasprite_origin);
+
+                                match selected_metasprite {
+                                    // The annotations stay, only the parts are replaced
+                                    Some(which) if !modifiers.alt => {
+                                        let from = project.metasprites[which].clone();
+                                        let metasprite = Metasprite { parts: metasprite.parts, ..from.clone() };
+
+                                        history.record(Edit::Metasprite { which, from, to: metasprite.clone() });
+                                        project.metasprites[which] = metasprite;
+                                    },
+                                    _ => {
+                                        history.record(Edit::AddMetasprite(metasprite.clone()));
+                                        project.metasprites.push(metasprite);
+                                        selected_metasprite = Some(project.metasprites.len() - 1);
+                                    }
+                                }
+
+                                println!("Stored metasprite {}", selected_metasprite.unwrap());
+                            },
+                            // Loads the next or previous metasprite into OAM
+                            VirtualKeyCode::Tab if !project.metasprites.is_empty() => {
+                                let count = project.metasprites.len();
+                                let which = match selected_metasprite {
+                                    Some(which) if modifiers.shift => (which + count - 1) % count,
+                                    Some(which) => (which + 1) % count,
+                                    None => 0,
+                                };
+
+                                replace_oam(
+                                    get_metasprite_oam(&project.metasprites[which], metasprite_origin), &mut project, &mut history
+                                );
+
+                                selected_metasprite = Some(which);
+                                selected_sprite = None;
+
+                                sprite_view_dirty = true;
+                            },
+                            // Mirrors the sprites around the origin, shift mirrors them vertically
+                            VirtualKeyCode::Q => {
+                                let metasprite = Metasprite::from_sprites(&project.oam.sprites, metasprite_origin)
+                                    .get_mirrored(!modifiers.shift, modifiers.shift, project.tall_sprites);
+
+                                replace_oam(get_metasprite_oam(&metasprite, metasprite_origin), &mut project, &mut history);
+                                selected_sprite = None;
+
+                                sprite_view_dirty = true;
+                            },
+                            // Adds the selected metasprite as a frame, or the selected tile when there is none
+                            // The frame starts a new animation when none is selected or alt is held
+                            VirtualKeyCode::J if !modifiers.shift => {
+                                let cel = match selected_metasprite {
+                                    Some(which) => Cel::Metasprite(which),
+                                    None => Cel::Tile { tile: selected_tile, attributes: selected_sprite_palette },
+                                };
+
+                                let frame = Frame { cel, duration: 8 };
+
+                                match selected_animation {
+                                    Some(which) if !modifiers.alt => {
+                                        let from = project.animations[which].clone();
+                                        project.animations[which].frames.push(frame);
+
+                                        history.record(Edit::Animation { which, from, to: project.animations[which].clone() });
+                                    },
+                                    _ => {
+                                        let animation = Animation { frames: vec![frame] };
+
+                                        history.record(Edit::AddAnimation(animation.clone()));
+                                        project.animations.push(animation);
+                                        selected_animation = Some(project.animations.len() - 1);
+                                    }
+                                }
+
+                                println!(
+                                    "Animation {} has {} frames",
+                                    selected_animation.unwrap(), project.animations[selected_animation.unwrap()].frames.len()
+                                );
+                            },
+                            // Removes the last frame
+                            VirtualKeyCode::J => {
+                                if let Some(which) = selected_animation {
+                                    let from = project.animations[which].clone();
+                                    project.animations[which].frames.pop();
+
+                                    history.record(Edit::Animation { which, from, to: project.animations[which].clone() });
+                                }
+                            },
+                            // Changes the duration of the last frame by one NTSC frame
+                            VirtualKeyCode::Minus | VirtualKeyCode::Equals => {
+                                if let Some(which) = selected_animation {
+                                    let from = project.animations[which].clone();
+
+                                    if let Some(frame) = project.animations[which].frames.last_mut() {
+                                        frame.duration = if code == VirtualKeyCode::Minus {
+                                            frame.duration.saturating_sub(1).max(1)
+                                        }
+                                        else {
+                                            frame.duration.saturating_add(1)
+                                        };
+
+                                        println!("Last frame lasts {} frames", frame.duration);
+                                    }
+
+                                    history.record(Edit::Animation { which, from, to: project.animations[which].clone() });
+                                }
+                            },
+                            VirtualKeyCode::Z if !project.animations.is_empty() => {
+                                let count = project.animations.len();
+
+                                selected_animation = Some(match selected_animation {
+                                    Some(which) if modifiers.shift => (which + count - 1) % count,
+                                    Some(which) => (which + 1) % count,
+                                    None => 0,
+                                });
+
+                                println!("Selected animation {}", selected_animation.unwrap());
+                            },
+                            VirtualKeyCode::Return => {
+                                playing = match playing {
+                                    Some(_) => None,
+                                    None => Some((Instant::now(), None)),
+                                };
+
+                                sprite_view_dirty = true;
+                            },
+                            VirtualKeyCode::F1 => export_animations(
+                                &project, metasprite_origin, &pattern_table, &samples, &palette
+                            ),
+                            VirtualKeyCode::F2 => export_palettes(&palette.colors, &samples.color_indices),
+                            VirtualKeyCode::F3 => export_fades(&samples.color_indices, fade_steps),
+                            VirtualKeyCode::F4 => {
+                                let matcher = ColorMatcher::new(&palette.colors, metric);
+
+                                match media::load_samples(Path::new("samples.gpl"), &matcher) {
+                                    Ok(color_indices) => {
+                                        history.begin();
+
+                                        for (which, (from, to)) in samples.color_indices.iter().zip(color_indices.iter()).enumerate() {
+                                            if from != to {
+                                                history.record(Edit::Sample { which, from: *from, to: *to });
+                                            }
+                                        }
+
+                                        history.end();
+
+                                        samples.set_color_indices(
+                                            color_indices, &palette.colors, queue.clone(), sampler.clone()
+                                        );
+
+                                        for (which, warning) in samples.get_warnings() {
+                                            println!("Sample {} uses a problematic color: {:?}", which, warning);
+                                        }
+
+                                        update_fade_preview(
+                                            &mut fade_preview, &samples, &palette, fade_steps, queue.clone(), sampler.clone()
+                                        );
+
+                                        nametable_dirty = true;
+                                        sprite_view_dirty = true;
+                                    },
+                                    Err(e) => println!("Failed to import samples: {:?}", e)
+                                }
+                            },
+                            VirtualKeyCode::F6 => {
+                                let grayscale = !palette.grayscale;
+
+                                palette.set_grayscale(grayscale, queue.clone(), sampler.clone());
+                                samples.set_grayscale(grayscale, &palette.colors, queue.clone(), sampler.clone());
+
+                                update_fade_preview(
+                                    &mut fade_preview, &samples, &palette, fade_steps, queue.clone(), sampler.clone()
+                                );
+
+                                nametable_dirty = true;
+                                sprite_view_dirty = true;
+                            },
+                            VirtualKeyCode::F7 => {
+                                let image_data = render::render_pattern_table(
+                                    &pattern_table.pixels,
+                                    samples::get_sub_palette(&samples.color_indices, 0),
+                                    &palette.colors,
+                                    palette.grayscale,
+                                    pattern_table.get_transparent_banks()
+                                );
+
+                                if let Err(e) = media::save_image(Path::new("pattern_table.png"), 256, 128, &image_data) {
+                                    println!("Failed to export pattern table: {:?}", e);
+                                }
+                            },
+                            // Deduplicates the page under the mouse, or the background page when the mouse is elsewhere
+                            VirtualKeyCode::F9 => {
+                                let page = match get_pattern_table_pixel(&mouse, &pattern_table, &view) {
+                                    Some((x, _)) => x as usize / 128,
+                                    None => project.background_page,
+                                };
+                                let freed = deduplicate(page, modifiers.shift, &mut pattern_table, &mut project, &mut history);
+
+                                println!("Freed {} tiles on page {}", freed, page);
+
+                                pattern_table.update_texture(&get_pattern_table_overlay(&selection), queue.clone(), sampler.clone());
+
+                                nametable_dirty = true;
+                                sprite_view_dirty = true;
+                            },
+                            // Slices sprite_sheet.png into metasprites, shift slices a grid the size of the selection
+                            VirtualKeyCode::D => {
+                                let (cell_width, cell_height) = match selection {
+                                    Some(selection) => {
+                                        let (_, _, width, height) = selection.get_bounds();
+                                        (width * 8, height * 8)
+                                    },
+                                    None => (16, 16),
+                                };
+
+                                let grid = if modifiers.shift { Some((cell_width, cell_height)) } else { None };
+
+                                match slice_sprite_sheet(grid, metric, &palette, &samples, &mut pattern_table, &mut project, &mut history) {
+                                    Ok(count) => println!("Sliced {} frames", count),
+                                    Err(e) => println!("Failed to slice the sprite sheet: {:?}", e),
+                                }
+
+                                pattern_table.update_texture(&get_pattern_table_overlay(&selection), queue.clone(), sampler.clone());
+
+                                nametable_dirty = true;
+                                sprite_view_dirty = true;
+                            },
+                            VirtualKeyCode::U if modifiers.shift => {
+                                let pages = vec![project.get_unused_tiles(0), project.get_unused_tiles(1)];
+
+                                if let Err(e) = media::save_unused_tiles(Path::new("unused_tiles.txt"), &pages) {
+                                    println!("Failed to export unused tiles: {:?}", e);
+                                }
+                            },
+                            VirtualKeyCode::U => show_unused = !show_unused,
+                            VirtualKeyCode::Key1 => tool_color = 0,
+                            VirtualKeyCode::Key2 => tool_color = 1,
+                            VirtualKeyCode::Key3 => tool_color = 2,
+                            VirtualKeyCode::Key4 => tool_color = 3,
+                            VirtualKeyCode::P => tool = Tool::Pencil,
+                            VirtualKeyCode::B => tool = Tool::Brush,
+                            VirtualKeyCode::L => tool = Tool::Line,
+                            VirtualKeyCode::S => tool = Tool::Select,
+                            VirtualKeyCode::M => tool = Tool::Reorder,
+                            VirtualKeyCode::R => {
+                                tool = if modifiers.shift { Tool::RectangleFill } else { Tool::Rectangle };
+                            },
+                            VirtualKeyCode::E => {
+                                tool = if modifiers.shift { Tool::EllipseFill } else { Tool::Ellipse };
+                            },
+                            VirtualKeyCode::G => {
+                                tool = Tool::Fill(if modifiers.shift { FillMode::Sheet } else { FillMode::Tile });
+                            },
+                            VirtualKeyCode::H | VirtualKeyCode::V | VirtualKeyCode::T | VirtualKeyCode::I
+                            | VirtualKeyCode::Left | VirtualKeyCode::Right | VirtualKeyCode::Up | VirtualKeyCode::Down => {
+                                let transform = match code {
+                                    VirtualKeyCode::H => Transform::FlipHorizontal,
+                                    VirtualKeyCode::V => Transform::FlipVertical,
+                                    VirtualKeyCode::T if modifiers.shift => Transform::RotateCounterClockwise,
+                                    VirtualKeyCode::T => Transform::RotateClockwise,
+                                    VirtualKeyCode::I => Transform::Invert,
+                                    VirtualKeyCode::Left => Transform::Shift(-1, 0),
+                                    VirtualKeyCode::Right => Transform::Shift(1, 0),
+                                    VirtualKeyCode::Up => Transform::Shift(0, -1),
+                                    _ => Transform::Shift(0, 1),
+                                };
+
+                                if let Some(region) = get_transform_region(&selection, &mouse, &pattern_table, &view) {
+                                    history.record(Edit::Pixels(pattern_table.transform(region, transform)));
+
+                                    pattern_table.update_texture(&get_pattern_table_overlay(&selection), queue.clone(), sampler.clone());
+
+                                    nametable_dirty = true;
+                                    sprite_view_dirty = true;
+                                }
+                            },
+                            VirtualKeyCode::K => {
+                                let swapped = swap_colors(
+                                    tool_color, modifiers.shift, None, &selection, &mouse, &view,
+                                    &mut pattern_table, &mut samples, &mut history
+                                );
+
+                                if swapped {
+                                    pattern_table.update_texture(&get_pattern_table_overlay(&selection), queue.clone(), sampler.clone());
+
+                                    nametable_dirty = true;
+                                    sprite_view_dirty = true;
+                                }
+                            },
+                            VirtualKeyCode::C => mode = Mode::Character,
+                            VirtualKeyCode::N => mode = Mode::Nametable,
+                            VirtualKeyCode::A => mode = Mode::Attribute,
+                            VirtualKeyCode::F8 => {
+                                palette.block_0d = !palette.block_0d;
+                            },
+                            VirtualKeyCode::F5 => {
+                                metric = match metric {
+                                    Metric::Ciede2000 => Metric::OkLab,
+                                    Metric::OkLab => Metric::Rgb,
+                                    Metric::Rgb => Metric::Ciede2000,
+                                };
+                            },
+                            VirtualKeyCode::LBracket | VirtualKeyCode::RBracket => {
+                                fade_steps = if code == VirtualKeyCode::LBracket {
+                                    fade_steps.max(2) - 1
+                                }
+                                else {
+                                    (fade_steps + 1).min(8)
+                                };
+
+                                update_fade_preview(
+                                    &mut fade_preview, &samples, &palette, fade_steps, queue.clone(), sampler.clone()
+                                );
+                            },
+                            _ => ()
+                        }
+                    }
                 },
                 _ => ()
             }
@@ -403,6 +1622,608 @@ fn get_sampler(device: Arc<Device>) -> Arc<Sampler> {
     ).unwrap()
 }
 
+fn export_palettes(colors: &[u8; 192], color_indices: &[u8; 26]) {
+    let master: Vec<u8> = (0..64).collect();
+    let sub_palettes = samples::get_sub_palettes(color_indices);
+
+    for (name, indices) in [("palette", &master), ("samples", &sub_palettes)].iter() {
+        let result = media::save_palette_gpl(Path::new(&format!("{}.gpl", name)), name, indices, colors)
+            .and_then(|_| media::save_palette_act(Path::new(&format!("{}.act", name)), indices, colors))
+            .and_then(|_| media::save_palette_jasc(Path::new(&format!("{}.pal", name)), indices, colors))
+            .and_then(|_| media::save_palette_hex(Path::new(&format!("{}.txt", name)), indices, colors));
+
+        if let Err(e) = result {
+            println!("Failed to export {} colors: {:?}", name, e);
+        }
+    }
+}
+
+fn export_fades(color_indices: &[u8; 26], steps: usize) {
+    let result = media::save_fade_table(
+        Path::new("fade_black.bin"),
+        &fade::get_fade(color_indices, FadeTarget::Black, steps)
+    ).and_then(|_| media::save_fade_table(
+        Path::new("fade_white.bin"),
+        &fade::get_fade(color_indices, FadeTarget::White, steps)
+    ));
+
+    if let Err(e) = result {
+        println!("Failed to export fades: {:?}", e);
+    }
+}
+
+// Writes ca65 sources for all graphics data along with graphics.inc, which imports their labels
+// Shift includes the CHR from graphics.chr instead of listing its bytes
+fn export_assembly(incbin: bool, pattern_table: &PatternTable, samples: &Samples, project: &Project) {
+    let mut chr = Source::new("CHARS");
+
+    if incbin {
+        chr.add_incbin("chr_data", "graphics.chr", pattern_table.bytes.len());
+    }
+    else {
+        chr.add_bytes("chr_data", &pattern_table.bytes);
+    }
+
+    // Attributes directly follow their nametable, so every map points at a whole nametable as the PPU holds it
+    let mut nametables = Source::new("RODATA");
+    let mut maps = vec![];
+
+    for (i, nametable) in project.nametables.iter().enumerate() {
+        nametables.add_bytes(&format!("nametable_{}", i), &nametable.tiles);
+        nametables.add_bytes(&format!("attributes_{}", i), &nametable.attribute_table.bytes);
+        maps.push(format!("nametable_{}", i));
+    }
+
+    nametables.add_addresses("maps", &maps);
+
+    let mut palettes = Source::new("RODATA");
+    palettes.add_bytes("palette", &samples::get_palette_ram(&samples.color_indices));
+
+    let mut metasprites = Source::new("RODATA");
+    let labels: Vec<String> = (0..project.metasprites.len()).map(|x| format!("metasprite_{}", x)).collect();
+
+    for (label, metasprite) in labels.iter().zip(project.metasprites.iter()) {
+        metasprites.add_bytes(label, &metasprite.to_bytes());
+        metasprites.add_bytes(&format!("{}_hitboxes", label), &metasprite.get_hitbox_bytes());
+        metasprites.add_bytes(&format!("{}_anchors", label), &metasprite.get_anchor_bytes());
+    }
+
+    metasprites.add_addresses("metasprites", &labels);
+    metasprites.add_addresses("metasprite_hitboxes", &labels.iter().map(|x| format!("{}_hitboxes", x)).collect::<Vec<String>>());
+    metasprites.add_addresses("metasprite_anchors", &labels.iter().map(|x| format!("{}_anchors", x)).collect::<Vec<String>>());
+
+    let mut animations = Source::new("RODATA");
+    let labels: Vec<String> = (0..project.animations.len()).map(|x| format!("animation_{}", x)).collect();
+
+    for (label, animation) in labels.iter().zip(project.animations.iter()) {
+        animations.add_bytes(&format!("{}_frames", label), &animation.get_frame_table());
+        animations.add_bytes(&format!("{}_kinds", label), &animation.get_kind_table());
+        animations.add_bytes(&format!("{}_attributes", label), &animation.get_attribute_table());
+        animations.add_bytes(&format!("{}_durations", label), &animation.get_duration_table());
+    }
+
+    animations.add_addresses("animation_frames", &labels.iter().map(|x| format!("{}_frames", x)).collect::<Vec<String>>());
+    animations.add_addresses("animation_kinds", &labels.iter().map(|x| format!("{}_kinds", x)).collect::<Vec<String>>());
+    animations.add_addresses("animation_attributes", &labels.iter().map(|x| format!("{}_attributes", x)).collect::<Vec<String>>());
+    animations.add_addresses("animation_durations", &labels.iter().map(|x| format!("{}_durations", x)).collect::<Vec<String>>());
+
+    let sources = [
+        ("chr.s", &chr),
+        ("nametables.s", &nametables),
+        ("palettes.s", &palettes),
+        ("metasprites.s", &metasprites),
+        ("animations.s", &animations),
+    ];
+
+    let mut result = if incbin {
+        media::save_table(Path::new("graphics.chr"), &pattern_table.bytes)
+    }
+    else {
+        Ok(())
+    };
+
+    for (path, source) in sources.iter() {
+        result = result.and_then(|_| media::save_text(Path::new(path), &source.to_string()));
+    }
+
+    let include = assembly::get_include("graphics", &sources.iter().map(|x| x.1).collect::<Vec<&Source>>());
+    result = result.and_then(|_| media::save_text(Path::new("graphics.inc"), &include));
+
+    if let Err(e) = result {
+        println!("Failed to export assembly: {:?}", e);
+    }
+}
+
+// Every animation is saved as a GIF that loops, along with its frame, kind, attribute and duration tables
+fn export_animations(
+    project: &Project,
+    origin: (i32, i32),
+    pattern_table: &PatternTable,
+    samples: &Samples,
+    palette: &Palette
+) {
+    for (which, animation) in project.animations.iter().enumerate() {
+        let frames: Vec<(Vec<u8>, u16)> = animation.get_gif_delays().into_iter().enumerate().map(|(i, delay)| {
+            (render_animation_frame(project, animation, i, origin, pattern_table, samples, palette), delay)
+        }).collect();
+
+        let result = media::save_gif(Path::new(&format!("animation_{}.gif", which)), 256, 240, &frames)
+            .and_then(|_| media::save_table(
+                Path::new(&format!("animation_{}_frames.bin", which)), &animation.get_frame_table()
+            ))
+            .and_then(|_| media::save_table(
+                Path::new(&format!("animation_{}_kinds.bin", which)), &animation.get_kind_table()
+            ))
+            .and_then(|_| media::save_table(
+                Path::new(&format!("animation_{}_attributes.bin", which)), &animation.get_attribute_table()
+            ))
+            .and_then(|_| media::save_table(
+                Path::new(&format!("animation_{}_durations.bin", which)), &animation.get_duration_table()
+            ));
+
+        if let Err(e) = result {
+            println!("Failed to export animation {}: {:?}", which, e);
+        }
+    }
+}
+
+// Shows the fade to white above the current samples and the fade to black below them
+fn update_fade_preview(
+    fade_preview: &mut Preview,
+    samples: &Samples,
+    palette: &Palette,
+    steps: usize,
+    queue: Arc<Queue>,
+    sampler: Arc<Sampler>
+) {
+    let mut rows: Vec<[u8; 26]> = fade::get_fade(&samples.color_indices, FadeTarget::White, steps);
+    rows.reverse();
+    rows.push(samples.color_indices);
+    rows.extend(fade::get_fade(&samples.color_indices, FadeTarget::Black, steps));
+
+    let image_data = render::render_samples(&rows, &palette.colors, palette.grayscale);
+
+    fade_preview.update_image(Vector2::new(26, rows.len() as u32), &image_data, queue, sampler);
+}
+
+fn get_pattern_table_pixel(mouse: &Mouse, pattern_table: &PatternTable, view: &View) -> Option<(i32, i32)> {
+    let surface_mouse = get_mouse_position_on_surface(
+        mouse.position,
+        Vector2::new(
+            pattern_table.surface.position.x,
+            pattern_table.surface.position.y
+        ),
+        pattern_table.surface.dimensions * view.zoom
+    );
+
+    get_cell_on_surface(surface_mouse, chr::SHEET_WIDTH, chr::SHEET_HEIGHT).map(|x| {
+        let (x, y) = chr::get_sheet_position(x % chr::SHEET_WIDTH, x / chr::SHEET_WIDTH, pattern_table.tall);
+
+        (x as i32, y as i32)
+    })
+}
+
+// Returns the index of the cell under the mouse for a surface divided in a grid
+fn get_cell_on_surface(surface_mouse: Vector2<f32>, columns: usize, rows: usize) -> Option<usize> {
+    if surface_mouse.x < 0.0 {
+        return None;
+    }
+
+    let x = ((surface_mouse.x * columns as f32) as usize).min(columns - 1);
+    let y = ((surface_mouse.y * rows as f32) as usize).min(rows - 1);
+
+    Some(y * columns + x)
+}
+
+// Outlines the selection when it is on the pattern table
+fn get_pattern_table_overlay(selection: &Option<Selection>) -> Vec<(usize, usize, u8)> {
+    match selection {
+        Some(selection) if selection.area == Area::PatternTable => selection.get_outline().iter()
+            .map(|&(x, y)| (x, y, pattern_table::HIGHLIGHT))
+            .collect(),
+        _ => vec![]
+    }
+}
+
+// Merges duplicate tiles into the first occurrence and clears them, returns the number of freed tiles
+// The background cannot be flipped, so flipped duplicates are only merged when nothing refers to them
+fn deduplicate(
+    page: usize,
+    flips: bool,
+    pattern_table: &mut PatternTable,
+    project: &mut Project,
+    history: &mut History
+) -> usize {
+    let usage = project.get_tile_usage(page);
+    let mut duplicates: Vec<(usize, usize, chr::Flip)> = chr::find_duplicates(&pattern_table.pixels, page, flips)
+        .into_iter()
+        .filter(|&(tile, _, flip)| flip == chr::Flip::None || usage[tile] == 0)
+        .collect();
+
+    let get_mapping = |duplicates: &[(usize, usize, chr::Flip)]| {
+        let mut mapping = chr::get_identity_mapping();
+
+        for &(tile, original, _) in duplicates.iter() {
+            mapping[tile] = original as u8;
+        }
+
+        mapping
+    };
+
+    // Duplicates in an 8x16 pair that cannot move along stay, which can split up other pairs in turn
+    loop {
+        let split = project.get_split_pairs(page, &get_mapping(&duplicates));
+
+        if split.is_empty() {
+            break;
+        }
+
+        duplicates.retain(|&(tile, _, _)| !split.contains(&tile));
+    }
+
+    // Blank tiles that nothing refers to were free already
+    let freed = duplicates.iter().filter(|&&(tile, _, _)| {
+        usage[tile] > 0 || chr::get_tile_pixels(&pattern_table.pixels, page * 256 + tile).iter().any(|x| *x != 0)
+    }).count();
+
+    let mapping = get_mapping(&duplicates);
+
+    history.begin();
+
+    for edit in project.remap_tiles(page, &mapping) {
+        history.record(edit);
+    }
+
+    let cleared: Vec<(usize, usize, u8)> = duplicates.iter().flat_map(|&(tile, _, _)| {
+        let (x, y) = chr::get_tile_origin(page * 256 + tile);
+        (0..64).map(move |i| (x + i % 8, y + i / 8, 0))
+    }).collect();
+
+    history.record(Edit::Pixels(pattern_table.set_pixels(&cleared)));
+    history.end();
+
+    freed
+}
+
+// Adds a metasprite for every frame of sprite_sheet.png, returns the number of frames
+// New tiles only go into tiles that are blank and unused, so nothing that is drawn gets overwritten
+fn slice_sprite_sheet(
+    grid: Option<(usize, usize)>,
+    metric: Metric,
+    palette: &Palette,
+    samples: &Samples,
+    pattern_table: &mut PatternTable,
+    project: &mut Project,
+    history: &mut History
+) -> std::io::Result<usize> {
+    let (width, height, image_data) = media::load_image(Path::new("sprite_sheet.png"))?;
+    let sheet = SpriteSheet::new(width, height, image_data);
+
+    let frames = match grid {
+        Some((cell_width, cell_height)) => sheet.get_grid_frames(cell_width, cell_height),
+        None => sheet.get_detected_frames(),
+    };
+
+    let free: Vec<usize> = (0..2).flat_map(|page| {
+        let usage = project.get_tile_usage(page);
+        (0..256).filter(move |x| usage[*x] == 0).map(move |x| page * 256 + x)
+    }).filter(|x| chr::get_tile_pixels(&pattern_table.pixels, *x).iter().all(|x| *x == 0)).collect();
+
+    let import = sheet.slice(
+        &frames,
+        &ColorMatcher::new(&palette.colors, metric),
+        &palette.colors,
+        &samples.color_indices,
+        &pattern_table.pixels,
+        project.sprite_page,
+        project.tall_sprites,
+        &free
+    );
+
+    // The tiles and the metasprites that use them are undone together
+    history.begin();
+    history.record(Edit::Pixels(pattern_table.set_pixels(&import.pixels)));
+
+    for (i, (metasprite, unrepresentable)) in import.metasprites.into_iter().zip(import.unrepresentable).enumerate() {
+        if !unrepresentable.is_empty() {
+            let colors: Vec<String> = unrepresentable.iter().map(|x| format!("${:02X}", x)).collect();
+            println!("Frame {} uses colors no sprite sub-palette holds: {}", i, colors.join(" "));
+        }
+
+        history.record(Edit::AddMetasprite(metasprite.clone()));
+        project.metasprites.push(metasprite);
+    }
+
+    history.end();
+
+    if import.dropped > 0 {
+        println!("Left out {} sprites, there are no free tiles left", import.dropped);
+    }
+
+    Ok(frames.len())
+}
+
+// Swaps the color under the mouse with the tool color in the selection or tile, or in the whole page
+// With sub-palettes the colors of one are swapped along, so the graphics look the same as before
+// Banks used for sprites take the sprite sub-palette, the others the background sub-palette
+fn swap_colors(
+    tool_color: u8,
+    whole_page: bool,
+    sub_palettes: Option<(u8, u8)>,
+    selection: &Option<Selection>,
+    mouse: &Mouse,
+    view: &View,
+    pattern_table: &mut PatternTable,
+    samples: &mut Samples,
+    history: &mut History
+) -> bool {
+    let (x, y) = match get_pattern_table_pixel(mouse, pattern_table, view) {
+        Some((x, y)) => (x as usize, y as usize),
+        None => return false
+    };
+
+    let region = if whole_page {
+        (x / 128 * 128, 0, 128, chr::SHEET_HEIGHT)
+    }
+    else {
+        get_transform_region(selection, mouse, pattern_table, view).unwrap_or((x / 8 * 8, y / 8 * 8, 8, 8))
+    };
+
+    let mut mapping = [0, 1, 2, 3];
+    mapping.swap(pattern_table.pixels[y * chr::SHEET_WIDTH + x] as usize, tool_color as usize);
+
+    history.begin();
+    history.record(Edit::Pixels(pattern_table.transform(region, Transform::Remap(mapping))));
+
+    if let Some((background, sprite)) = sub_palettes {
+        let which = if pattern_table.sprite_banks[x / 128] { 4 + sprite as usize } else { background as usize };
+
+        match samples::get_remapped_color_indices(&samples.color_indices, which, mapping) {
+            Some(color_indices) => {
+                for (which, (from, to)) in samples.color_indices.iter().zip(color_indices.iter()).enumerate() {
+                    if from != to {
+                        history.record(Edit::Sample { which, from: *from, to: *to });
+                    }
+                }
+
+                samples.color_indices = color_indices;
+            },
+            None => println!("The universal background color cannot follow the remap")
+        }
+    }
+
+    history.end();
+
+    true
+}
+
+// The selected block when it is on the pattern table, otherwise the tile under the mouse
+fn get_transform_region(
+    selection: &Option<Selection>,
+    mouse: &Mouse,
+    pattern_table: &PatternTable,
+    view: &View
+) -> Option<(usize, usize, usize, usize)> {
+    match selection {
+        Some(selection) if selection.area == Area::PatternTable => {
+            let (x, y, width, height) = selection.get_bounds();
+
+            Some((x * 8, y * 8, width * 8, height * 8))
+        },
+        _ => get_pattern_table_pixel(mouse, pattern_table, view)
+            .map(|(x, y)| (x as usize / 8 * 8, y as usize / 8 * 8, 8, 8))
+    }
+}
+
+fn get_nametable_tile(mouse: &Mouse, nametable_view: &Preview) -> Option<(usize, usize)> {
+    get_preview_cell(mouse, nametable_view, nametable::WIDTH, nametable::HEIGHT)
+}
+
+fn get_sprite_view_pixel(mouse: &Mouse, sprite_view: &Preview) -> Option<(usize, usize)> {
+    get_preview_cell(mouse, sprite_view, nametable::WIDTH * 8, nametable::HEIGHT * 8)
+}
+
+fn get_preview_cell(mouse: &Mouse, preview: &Preview, columns: usize, rows: usize) -> Option<(usize, usize)> {
+    let surface_mouse = get_mouse_position_on_surface(
+        mouse.position,
+        Vector2::new(
+            preview.surface.position.x,
+            preview.surface.position.y
+        ),
+        preview.surface.dimensions
+    );
+
+    get_cell_on_surface(surface_mouse, columns, rows).map(|x| (x % columns, x / columns))
+}
+
+// The overlay shows tiles without committing them, the selection is outlined when it is on the nametable
+fn update_nametable_view(
+    nametable_view: &mut Preview,
+    project: &Project,
+    overlay: &[(usize, usize, u8)],
+    selection: &Option<Selection>,
+    show_sprites: bool,
+    limit: bool,
+    pattern_table: &PatternTable,
+    samples: &Samples,
+    palette: &Palette,
+    queue: Arc<Queue>,
+    sampler: Arc<Sampler>
+) {
+    let mut nametable = project.nametables[0];
+
+    for &(x, y, tile) in overlay {
+        nametable.set_tile(x, y, tile);
+    }
+
+    let mut image_data = if show_sprites {
+        render::render_scene(
+            &nametable,
+            &project.oam,
+            &pattern_table.pixels,
+            project.background_page,
+            project.sprite_page,
+            project.tall_sprites,
+            limit,
+            &samples.color_indices,
+            &palette.colors,
+            palette.grayscale
+        )
+    }
+    else {
+        render::render_nametable(
+            &nametable,
+            &pattern_table.pixels,
+            project.background_page,
+            &samples.color_indices,
+            &palette.colors,
+            palette.grayscale
+        )
+    };
+
+    if let Some(selection) = selection.filter(|x| x.area == Area::Nametable) {
+        render::render_outline(&mut image_data, nametable::WIDTH * 8, &selection.get_outline());
+    }
+
+    if let Some((x, y)) = project.get_sprite_zero_hit(&pattern_table.pixels) {
+        render::render_outline(&mut image_data, nametable::WIDTH * 8, &get_cross((x as i32, y as i32), 3));
+    }
+
+    nametable_view.update_image(Vector2::new(256, 240), &image_data, queue, sampler);
+}
+
+// Places the parts in the first sprites and hides the others
+fn get_metasprite_oam(metasprite: &Metasprite, origin: (i32, i32)) -> Oam {
+    let mut oam = Oam::new();
+
+    for (sprite, part) in oam.sprites.iter_mut().zip(metasprite.to_sprites(origin)) {
+        *sprite = part;
+    }
+
+    oam
+}
+
+// Records the changes to every sprite as one step
+fn replace_oam(oam: Oam, project: &mut Project, history: &mut History) {
+    history.begin();
+
+    for (which, (from, to)) in project.oam.sprites.iter().zip(oam.sprites.iter()).enumerate() {
+        if from != to {
+            history.record(Edit::Sprite { which, from: *from, to: *to });
+        }
+    }
+
+    history.end();
+
+    project.oam = oam;
+}
+
+// The selected sprite is outlined
+fn update_sprite_view(
+    sprite_view: &mut Preview,
+    project: &Project,
+    selected_sprite: Option<usize>,
+    selected_metasprite: Option<usize>,
+    origin: (i32, i32),
+    limit: bool,
+    pattern_table: &PatternTable,
+    samples: &Samples,
+    palette: &Palette,
+    queue: Arc<Queue>,
+    sampler: Arc<Sampler>
+) {
+    let (width, height) = (nametable::WIDTH * 8, nametable::HEIGHT * 8);
+    let mut image_data = render::render_sprites(
+        &project.oam,
+        &pattern_table.pixels,
+        project.sprite_page,
+        project.tall_sprites,
+        limit,
+        pattern_table.checkerboard,
+        &samples.color_indices,
+        &palette.colors,
+        palette.grayscale
+    );
+
+    if let Some(sprite) = selected_sprite.map(|x| project.oam.sprites[x]).filter(|x| !x.is_hidden()) {
+        let (x0, y0) = (sprite.x as i32, sprite.get_top() as i32);
+        let bottom = y0 + oam::get_height(project.tall_sprites) as i32 - 1;
+        let outline: Vec<(usize, usize)> = tool::get_rectangle((x0, y0), (x0 + 7, bottom), false)
+            .iter()
+            .map(|&(x, y)| (x as usize, y as usize))
+            .filter(|&(x, y)| x < width && y < height)
+            .collect();
+
+        render::render_outline(&mut image_data, width, &outline);
+    }
+
+    let lines: Vec<usize> = project.oam.get_overflow(project.tall_sprites).iter().map(|x| x.0).collect();
+    render::render_marked_lines(&mut image_data, width, &lines);
+
+    // Hitboxes are red, hurtboxes green and anchors yellow
+    if let Some(metasprite) = selected_metasprite.map(|x| &project.metasprites[x]) {
+        for hitbox in metasprite.hitboxes.iter() {
+            let (x0, y0) = (origin.0 + hitbox.dx as i32, origin.1 + hitbox.dy as i32);
+            let (x1, y1) = (x0 + hitbox.width as i32 - 1, y0 + hitbox.height as i32 - 1);
+            let outline: Vec<(usize, usize)> = tool::get_rectangle((x0, y0), (x1, y1), false)
+                .iter()
+                .filter(|&&(x, y)| x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height)
+                .map(|&(x, y)| (x as usize, y as usize))
+                .collect();
+
+            let color = match hitbox.kind {
+                BoxKind::Hitbox => [255, 0, 0, 255],
+                BoxKind::Hurtbox => [0, 255, 0, 255],
+            };
+
+            render::render_colored_outline(&mut image_data, width, &outline, color);
+        }
+
+        for &(dx, dy) in metasprite.anchors.iter().flatten() {
+            let anchor = (origin.0 + dx as i32, origin.1 + dy as i32);
+            render::render_colored_outline(&mut image_data, width, &get_cross(anchor, 1), [255, 255, 0, 255]);
+        }
+    }
+
+    // The origin of metasprites is marked with a small cross
+    render::render_outline(&mut image_data, width, &get_cross(origin, 2));
+
+    sprite_view.update_image(Vector2::new(width as u32, height as u32), &image_data, queue, sampler);
+}
+
+// Pixels of a cross around the center, which itself is left out so it stays visible
+fn get_cross(center: (i32, i32), size: i32) -> Vec<(usize, usize)> {
+    let (width, height) = ((nametable::WIDTH * 8) as i32, (nametable::HEIGHT * 8) as i32);
+
+    (-size..=size).flat_map(|x| vec![(center.0 + x, center.1), (center.0, center.1 + x)])
+        .filter(|&(x, y)| x >= 0 && y >= 0 && x < width && y < height && (x, y) != center)
+        .map(|(x, y)| (x as usize, y as usize))
+        .collect()
+}
+
+// Frames are shown without the scanline limit, like the engine would draw them on an empty screen
+fn render_animation_frame(
+    project: &Project,
+    animation: &Animation,
+    frame: usize,
+    origin: (i32, i32),
+    pattern_table: &PatternTable,
+    samples: &Samples,
+    palette: &Palette
+) -> Vec<u8> {
+    render::render_sprites(
+        &get_metasprite_oam(&animation.get_metasprite(frame, &project.metasprites), origin),
+        &pattern_table.pixels,
+        project.sprite_page,
+        project.tall_sprites,
+        false,
+        false,
+        &samples.color_indices,
+        &palette.colors,
+        palette.grayscale
+    )
+}
+
 // TODO: Move to system module
 fn get_mouse_position_on_surface(
     mouse_position: Vector2<f32>,
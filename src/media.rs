@@ -5,9 +5,19 @@
 // use crate::nametable::Nametable;
 // use crate::samples::Samples;
 
+use crate::color::ColorMatcher;
+use crate::metasprite::Metasprite;
+use crate::samples;
+
+use gif::SetParameter;
+
 use std::fs::File;
 
 use std::io::{
+    BufRead,
+    BufReader,
+    Error,
+    ErrorKind,
     Result,
     prelude::*
 };
@@ -53,6 +63,288 @@ pub fn load_pattern_table_bytes_and_pixels(path: &Path) -> Result<([u8; 8192], [
     Ok((buffer, pixels))
 }
 
+pub fn load_palette(path: &Path) -> Result<[u8; 192]> {
+    let colors = load_colors(path)?;
+
+    if colors.len() < 192 {
+        return Err(Error::new(ErrorKind::InvalidData, "Palette holds fewer than 64 colors"));
+    }
+
+    let mut palette: [u8; 192] = [0u8; 192];
+    palette.copy_from_slice(&colors[..192]);
+
+    Ok(palette)
+}
+
+// Arbitrary colors are snapped to the nearest usable entry of the master palette
+pub fn load_samples(path: &Path, matcher: &ColorMatcher) -> Result<[u8; 26]> {
+    let colors = load_colors(path)?;
+
+    if colors.len() < 96 {
+        return Err(Error::new(ErrorKind::InvalidData, "Palette holds fewer than 32 colors"));
+    }
+
+    let sub_palettes: Vec<u8> = colors.chunks(3)
+        .take(32)
+        .map(|x| matcher.get_nearest([x[0], x[1], x[2]]))
+        .collect();
+
+    Ok(samples::get_color_indices(&sub_palettes))
+}
+
+// Returns RGB triplets
+pub fn load_colors(path: &Path) -> Result<Vec<u8>> {
+    match path.extension().and_then(|x| x.to_str()) {
+        Some("gpl") => load_colors_gpl(path),
+        Some("act") => load_colors_act(path),
+        _ => Err(Error::new(ErrorKind::InvalidInput, "Unsupported palette format"))
+    }
+}
+
+fn load_colors_gpl(path: &Path) -> Result<Vec<u8>> {
+    let file = File::open(&path)?;
+    let mut colors: Vec<u8> = vec![];
+
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let line = line.trim();
+
+        if line.is_empty()
+        || line.starts_with('#')
+        || line.starts_with("GIMP Palette")
+        || line.starts_with("Name:")
+        || line.starts_with("Columns:") {
+            continue;
+        }
+
+        let channels: Vec<u8> = line.split_whitespace()
+            .take(3)
+            .map(|x| x.parse::<u8>())
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "Invalid color in GIMP palette"))?;
+
+        if channels.len() < 3 {
+            return Err(Error::new(ErrorKind::InvalidData, "Incomplete color in GIMP palette"));
+        }
+
+        colors.extend(channels);
+    }
+
+    Ok(colors)
+}
+
+fn load_colors_act(path: &Path) -> Result<Vec<u8>> {
+    let mut file = File::open(&path)?;
+    let mut buffer: Vec<u8> = vec![];
+
+    file.read_to_end(&mut buffer)?;
+
+    // The optional trailer holds the number of colors in use
+    let count = if buffer.len() >= 770 {
+        (buffer[768] as usize) << 8 | buffer[769] as usize
+    }
+    else {
+        buffer.len() / 3
+    };
+
+    let end = (count * 3).min(buffer.len()).min(768);
+    buffer.truncate(end);
+
+    Ok(buffer)
+}
+
+// Every export writes a list of NES color indices as looked up in a master palette
+pub fn save_palette_gpl(path: &Path, name: &str, color_indices: &[u8], colors: &[u8; 192]) -> Result<()> {
+    let mut file = File::create(path)?;
+
+    writeln!(file, "GIMP Palette")?;
+    writeln!(file, "Name: {}", name)?;
+    writeln!(file, "Columns: 16")?;
+    writeln!(file, "#")?;
+
+    for x in color_indices {
+        let index = *x as usize * 3;
+
+        writeln!(
+            file,
+            "{:3} {:3} {:3}\t${:02X}",
+            colors[index], colors[index + 1], colors[index + 2], x
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn save_palette_act(path: &Path, color_indices: &[u8], colors: &[u8; 192]) -> Result<()> {
+    let mut file = File::create(path)?;
+    let mut buffer: [u8; 772] = [0u8; 772];
+
+    for (i, x) in color_indices.iter().take(256).enumerate() {
+        let index = *x as usize * 3;
+        buffer[i * 3..i * 3 + 3].copy_from_slice(&colors[index..index + 3]);
+    }
+
+    // Color count followed by "no transparent color"
+    let count = color_indices.len().min(256);
+    buffer[768] = (count >> 8) as u8;
+    buffer[769] = count as u8;
+    buffer[770] = 0xFF;
+    buffer[771] = 0xFF;
+
+    file.write_all(&buffer)
+}
+
+pub fn save_palette_jasc(path: &Path, color_indices: &[u8], colors: &[u8; 192]) -> Result<()> {
+    let mut file = File::create(path)?;
+
+    write!(file, "JASC-PAL\r\n0100\r\n{}\r\n", color_indices.len())?;
+
+    for x in color_indices {
+        let index = *x as usize * 3;
+        write!(file, "{} {} {}\r\n", colors[index], colors[index + 1], colors[index + 2])?;
+    }
+
+    Ok(())
+}
+
+pub fn save_palette_hex(path: &Path, color_indices: &[u8], colors: &[u8; 192]) -> Result<()> {
+    let mut file = File::create(path)?;
+
+    for x in color_indices {
+        let index = *x as usize * 3;
+        writeln!(file, "{:02X}{:02X}{:02X}", colors[index], colors[index + 1], colors[index + 2])?;
+    }
+
+    Ok(())
+}
+
+// Every step is written as a full 32 byte palette RAM image
+pub fn save_fade_table(path: &Path, steps: &[[u8; 26]]) -> Result<()> {
+    let mut file = File::create(path)?;
+
+    for step in steps {
+        file.write_all(&samples::get_palette_ram(step))?;
+    }
+
+    Ok(())
+}
+
+pub fn load_oam(path: &Path) -> Result<[u8; 256]> {
+    let mut file = File::open(path)?;
+    let mut buffer: Vec<u8> = vec![];
+    file.read_to_end(&mut buffer)?;
+
+    if buffer.len() != 256 {
+        return Err(Error::new(ErrorKind::InvalidData, "OAM dumps are 256 bytes"));
+    }
+
+    let mut bytes = [0u8; 256];
+    bytes.copy_from_slice(&buffer);
+
+    Ok(bytes)
+}
+
+pub fn save_oam(path: &Path, bytes: &[u8; 256]) -> Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(bytes)?;
+
+    Ok(())
+}
+
+// Metasprites follow each other, each ending in its own terminator
+pub fn save_metasprites(path: &Path, metasprites: &[Metasprite]) -> Result<()> {
+    let mut file = File::create(path)?;
+
+    for metasprite in metasprites {
+        file.write_all(&metasprite.to_bytes())?;
+    }
+
+    Ok(())
+}
+
+// Follows the order of the metasprites, so the tables line up with save_metasprites
+pub fn save_hitboxes(path: &Path, metasprites: &[Metasprite]) -> Result<()> {
+    let mut file = File::create(path)?;
+
+    for metasprite in metasprites {
+        file.write_all(&metasprite.get_hitbox_bytes())?;
+    }
+
+    Ok(())
+}
+
+pub fn save_anchors(path: &Path, metasprites: &[Metasprite]) -> Result<()> {
+    let mut file = File::create(path)?;
+
+    for metasprite in metasprites {
+        file.write_all(&metasprite.get_anchor_bytes())?;
+    }
+
+    Ok(())
+}
+
+// Lists the unused tiles of every page, sixteen per line
+pub fn save_unused_tiles(path: &Path, pages: &[Vec<u8>]) -> Result<()> {
+    let mut file = File::create(path)?;
+
+    for (page, tiles) in pages.iter().enumerate() {
+        writeln!(file, "Page {}: {} unused", page, tiles.len())?;
+
+        for row in tiles.chunks(16) {
+            let row: Vec<String> = row.iter().map(|x| format!("${:02X}", x)).collect();
+            writeln!(file, "{}", row.join(" "))?;
+        }
+    }
+
+    Ok(())
+}
+
+// Tables for the engine are written as plain bytes
+pub fn save_table(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(bytes)?;
+
+    Ok(())
+}
+
+pub fn save_text(path: &Path, text: &str) -> Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(text.as_bytes())?;
+
+    Ok(())
+}
+
+// Every frame is an RGBA image with its delay in hundredths of a second
+pub fn save_gif(path: &Path, width: u16, height: u16, frames: &[(Vec<u8>, u16)]) -> Result<()> {
+    let file = File::create(path)?;
+    let mut encoder = gif::Encoder::new(file, width, height, &[])?;
+
+    // Without the NETSCAPE extension most viewers play the animation only once
+    encoder.set(gif::Repeat::Infinite)?;
+
+    for (image_data, delay) in frames {
+        let mut image_data = image_data.clone();
+        let mut frame = gif::Frame::from_rgba(width, height, &mut image_data);
+        frame.delay = *delay;
+
+        encoder.write_frame(&frame)?;
+    }
+
+    Ok(())
+}
+
+// Returns the size and the RGBA data of an image in any format the image crate reads
+pub fn load_image(path: &Path) -> Result<(usize, usize, Vec<u8>)> {
+    let image = image::open(path).map_err(|e| Error::new(ErrorKind::InvalidData, format!("{:?}", e)))?.to_rgba();
+    let (width, height) = image.dimensions();
+
+    Ok((width as usize, height as usize, image.into_raw()))
+}
+
+pub fn save_image(path: &Path, width: u32, height: u32, image_data: &[u8]) -> Result<()> {
+    image::save_buffer(path, image_data, width, height, image::RGBA(8))
+}
+
 // pub fn load_samples(path: &Path) -> Result<Samples> {
 //     let mut file = File::open(&path)?;
 //     let mut samples: [u8; 26] = [0u8; 26];
@@ -0,0 +1,190 @@
+// Copyright 2019, Sjors van Gelderen
+
+use crate::oam::{
+    self,
+    Sprite,
+};
+
+// Ends the list of parts, which is why parts cannot be offset by exactly -128 horizontally
+pub const TERMINATOR: u8 = 0x80;
+
+// Anchors have fixed slots, so every metasprite exports the same layout
+pub const ANCHORS: [&str; 4] = ["feet", "weapon", "head", "effect"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BoxKind {
+    Hitbox,
+    Hurtbox,
+}
+
+// A rectangle relative to the origin of the metasprite, in pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hitbox {
+    pub kind: BoxKind,
+    pub dx: i8,
+    pub dy: i8,
+    pub width: u8,
+    pub height: u8,
+}
+
+impl Hitbox {
+    // Spans both corners, which are given relative to the origin
+    pub fn from_corners(kind: BoxKind, a: (i32, i32), b: (i32, i32)) -> Hitbox {
+        let (x0, y0) = (a.0.min(b.0).max(-128), a.1.min(b.1).max(-128));
+        let (x1, y1) = (a.0.max(b.0).min(127), a.1.max(b.1).min(127));
+
+        Hitbox {
+            kind,
+            dx: x0 as i8,
+            dy: y0 as i8,
+            // Spanning the whole range of offsets would take 256, one more than fits
+            width: (x1 - x0 + 1).min(255) as u8,
+            height: (y1 - y0 + 1).min(255) as u8,
+        }
+    }
+
+    pub fn contains(&self, dx: i32, dy: i32) -> bool {
+        dx >= self.dx as i32 && dx < self.dx as i32 + self.width as i32
+            && dy >= self.dy as i32 && dy < self.dy as i32 + self.height as i32
+    }
+}
+
+// A hardware sprite relative to the origin of the metasprite
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Part {
+    pub dx: i8,
+    pub dy: i8,
+    pub tile: u8,
+    pub attributes: u8,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Metasprite {
+    pub parts: Vec<Part>,
+    pub hitboxes: Vec<Hitbox>,
+    // Points relative to the origin, in the order of ANCHORS
+    pub anchors: [Option<(i8, i8)>; 4],
+}
+
+impl Metasprite {
+    pub fn new(parts: Vec<Part>) -> Metasprite {
+        Metasprite {
+            parts,
+            hitboxes: vec![],
+            anchors: [None; 4],
+        }
+    }
+
+    // Takes the visible sprites, offsets that do not fit are clamped
+    pub fn from_sprites(sprites: &[Sprite], origin: (i32, i32)) -> Metasprite {
+        let parts = sprites.iter().filter(|x| !x.is_hidden()).map(|x| {
+            let dx = (x.x as i32 - origin.0).max(-127).min(127);
+            let dy = (x.get_top() as i32 - origin.1).max(-128).min(127);
+
+            Part {
+                dx: dx as i8,
+                dy: dy as i8,
+                tile: x.tile,
+                attributes: x.attributes,
+            }
+        }).collect();
+
+        Metasprite::new(parts)
+    }
+
+    // Parts that fall off the screen are hidden, like an engine would skip them
+    pub fn to_sprites(&self, origin: (i32, i32)) -> Vec<Sprite> {
+        self.parts.iter().map(|x| {
+            let (left, top) = (origin.0 + x.dx as i32, origin.1 + x.dy as i32);
+
+            if left < 0 || left > 255 || top < 1 || top > 239 {
+                Sprite::hidden()
+            }
+            else {
+                Sprite {
+                    y: (top - 1) as u8,
+                    tile: x.tile,
+                    attributes: x.attributes,
+                    x: left as u8,
+                }
+            }
+        }).collect()
+    }
+
+    // Mirrors around the origin, flipping every part along with its position
+    // A pixel at offset x ends up at -1 - x, so the annotations cover the same pixels of the mirrored image
+    pub fn get_mirrored(&self, horizontal: bool, vertical: bool, tall: bool) -> Metasprite {
+        let parts = self.parts.iter().map(|x| {
+            let mut part = *x;
+
+            if horizontal {
+                part.dx = (-8 - x.dx as i32).max(-127).min(127) as i8;
+                part.attributes ^= oam::FLIP_HORIZONTAL;
+            }
+
+            if vertical {
+                part.dy = (-(oam::get_height(tall) as i32) - x.dy as i32).max(-128).min(127) as i8;
+                part.attributes ^= oam::FLIP_VERTICAL;
+            }
+
+            part
+        }).collect();
+
+        let hitboxes = self.hitboxes.iter().map(|x| {
+            let mut hitbox = *x;
+
+            if horizontal {
+                hitbox.dx = (-(x.dx as i32) - x.width as i32).max(-128) as i8;
+            }
+
+            if vertical {
+                hitbox.dy = (-(x.dy as i32) - x.height as i32).max(-128) as i8;
+            }
+
+            hitbox
+        }).collect();
+
+        let mut anchors = self.anchors;
+
+        for anchor in anchors.iter_mut() {
+            if let Some((dx, dy)) = *anchor {
+                *anchor = Some((
+                    if horizontal { (-1 - dx as i32).max(-127) as i8 } else { dx },
+                    if vertical { (-1 - dy as i32).max(-127) as i8 } else { dy },
+                ));
+            }
+        }
+
+        Metasprite {
+            parts,
+            hitboxes,
+            anchors,
+        }
+    }
+
+    // Four bytes per part in the order dx, dy, tile, attributes, followed by the terminator
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.parts.iter()
+            .flat_map(|x| vec![x.dx as u8, x.dy as u8, x.tile, x.attributes])
+            .chain(std::iter::once(TERMINATOR))
+            .collect()
+    }
+
+    // Five bytes per box in the order kind, dx, dy, width, height, followed by the terminator
+    pub fn get_hitbox_bytes(&self) -> Vec<u8> {
+        self.hitboxes.iter()
+            .flat_map(|x| vec![x.kind as u8, x.dx as u8, x.dy as u8, x.width, x.height])
+            .chain(std::iter::once(TERMINATOR))
+            .collect()
+    }
+
+    // Two bytes per slot in the order of ANCHORS, missing anchors hold the terminator in both
+    pub fn get_anchor_bytes(&self) -> Vec<u8> {
+        self.anchors.iter()
+            .flat_map(|x| match x {
+                Some((dx, dy)) => vec![*dx as u8, *dy as u8],
+                None => vec![TERMINATOR, TERMINATOR],
+            })
+            .collect()
+    }
+}
@@ -1,7 +1,9 @@
 // Copyright 2019, Sjors van Gelderen
 
-// enum Mode {
-//     Character,
-//     Nametable,
-//     Attribute
-// }
\ No newline at end of file
+// Decides what clicking the nametable view does
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Character,
+    Nametable,
+    Attribute,
+}
@@ -1,13 +1,58 @@
 // Copyright 2019, Sjors van Gelderen
 
-// pub struct Nametable {
+use crate::attribute_table::AttributeTable;
+use crate::chr;
 
-// }
+pub const WIDTH: usize = 32;
+pub const HEIGHT: usize = 30;
 
-// impl Nametable {
-//     pub fn zero() -> Nametable {
-//         Nametable {
-            
-//         }
-//     }
-// }
\ No newline at end of file
+// Tile indices refer to the page that is used for the background
+#[derive(Clone, Copy)]
+pub struct Nametable {
+    pub tiles: [u8; 960],
+    pub attribute_table: AttributeTable,
+}
+
+impl Nametable {
+    pub fn zero() -> Nametable {
+        Nametable {
+            tiles: [0u8; 960],
+            attribute_table: AttributeTable::zero(),
+        }
+    }
+
+    pub fn get_tile(&self, x: usize, y: usize) -> u8 {
+        self.tiles[y * WIDTH + x]
+    }
+
+    // Color index of a pixel of the screen, taken from the tile on the given page
+    pub fn get_pixel(&self, pixels: &[u8; 32768], page: usize, x: usize, y: usize) -> u8 {
+        let (origin_x, origin_y) = chr::get_tile_origin(page * 256 + self.get_tile(x / 8, y / 8) as usize);
+
+        pixels[(origin_y + y % 8) * chr::SHEET_WIDTH + origin_x + x % 8]
+    }
+
+    // Returns the previous tile
+    pub fn set_tile(&mut self, x: usize, y: usize, tile: u8) -> u8 {
+        let previous = self.tiles[y * WIDTH + x];
+        self.tiles[y * WIDTH + x] = tile;
+
+        previous
+    }
+
+    // Points every tile at its new slot, returns the changed tiles with their previous and new index
+    pub fn remap_tiles(&mut self, mapping: &[u8; 256]) -> Vec<(usize, usize, u8, u8)> {
+        let mut changes = vec![];
+
+        for (i, tile) in self.tiles.iter_mut().enumerate() {
+            let previous = *tile;
+            *tile = mapping[previous as usize];
+
+            if *tile != previous {
+                changes.push((i % WIDTH, i / WIDTH, previous, *tile));
+            }
+        }
+
+        changes
+    }
+}
@@ -0,0 +1,170 @@
+// Copyright 2019, Sjors van Gelderen
+
+// Object attribute memory, 64 sprites of four bytes each in the order the PPU reads them
+
+use crate::chr;
+
+pub const SUB_PALETTE: u8 = 0b0000_0011;
+pub const BEHIND_BACKGROUND: u8 = 0b0010_0000;
+pub const FLIP_HORIZONTAL: u8 = 0b0100_0000;
+pub const FLIP_VERTICAL: u8 = 0b1000_0000;
+
+// The PPU drops any further sprites on a scanline
+pub const SPRITES_PER_SCANLINE: usize = 8;
+
+// Sprites are 8x16 when bit 5 of PPUCTRL is set
+pub fn get_height(tall: bool) -> usize {
+    if tall { 16 } else { 8 }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprite {
+    pub y: u8,
+    pub tile: u8,
+    pub attributes: u8,
+    pub x: u8,
+}
+
+impl Sprite {
+    // Any Y coordinate past the last scanline hides the sprite, $FF is the conventional one
+    pub fn hidden() -> Sprite {
+        Sprite {
+            y: 0xFF,
+            tile: 0,
+            attributes: 0,
+            x: 0,
+        }
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.y >= 0xEF
+    }
+
+    // Sprites are drawn one scanline below their Y coordinate
+    pub fn get_top(&self) -> usize {
+        self.y as usize + 1
+    }
+
+    pub fn get_sub_palette(&self) -> u8 {
+        self.attributes & SUB_PALETTE
+    }
+
+    pub fn set_sub_palette(self, sub_palette: u8) -> Sprite {
+        Sprite {
+            attributes: (self.attributes & !SUB_PALETTE) | (sub_palette & SUB_PALETTE),
+            ..self
+        }
+    }
+
+    pub fn toggle_attribute(self, attribute: u8) -> Sprite {
+        Sprite {
+            attributes: self.attributes ^ attribute,
+            ..self
+        }
+    }
+
+    pub fn is_flipped_horizontally(&self) -> bool {
+        self.attributes & FLIP_HORIZONTAL > 0
+    }
+
+    pub fn is_flipped_vertically(&self) -> bool {
+        self.attributes & FLIP_VERTICAL > 0
+    }
+
+    // Returns the page and tile for every 8 pixels of height
+    // Tall sprites take the page from bit 0 of the tile index and a pair of tiles from the rest
+    pub fn get_tiles(&self, page: usize, tall: bool) -> Vec<(usize, u8)> {
+        if tall {
+            let top = self.tile & 0xFE;
+            vec![((self.tile & 1) as usize, top), ((self.tile & 1) as usize, top + 1)]
+        }
+        else {
+            vec![(page, self.tile)]
+        }
+    }
+
+    // Color index of a pixel relative to the top left of the sprite, with flipping applied
+    // Flipping a tall sprite vertically also swaps its tiles
+    pub fn get_pixel(&self, pixels: &[u8; 32768], page: usize, x: usize, y: usize, tall: bool) -> u8 {
+        let x = if self.is_flipped_horizontally() { 7 - x } else { x };
+        let y = if self.is_flipped_vertically() { get_height(tall) - 1 - y } else { y };
+        let (page, tile) = self.get_tiles(page, tall)[y / 8];
+        let (origin_x, origin_y) = chr::get_tile_origin(page * 256 + tile as usize);
+
+        pixels[(origin_y + y % 8) * chr::SHEET_WIDTH + origin_x + x]
+    }
+
+    pub fn contains(&self, x: usize, y: usize, tall: bool) -> bool {
+        !self.is_hidden()
+            && x >= self.x as usize && x < self.x as usize + 8
+            && y >= self.get_top() && y < self.get_top() + get_height(tall)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Oam {
+    pub sprites: [Sprite; 64],
+}
+
+impl Oam {
+    pub fn new() -> Oam {
+        Oam {
+            sprites: [Sprite::hidden(); 64],
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8; 256]) -> Oam {
+        let mut oam = Oam::new();
+
+        for (sprite, x) in oam.sprites.iter_mut().zip(bytes.chunks(4)) {
+            *sprite = Sprite {
+                y: x[0],
+                tile: x[1],
+                attributes: x[2],
+                x: x[3],
+            };
+        }
+
+        oam
+    }
+
+    pub fn to_bytes(&self) -> [u8; 256] {
+        let mut bytes = [0u8; 256];
+
+        for (sprite, x) in self.sprites.iter().zip(bytes.chunks_mut(4)) {
+            x.copy_from_slice(&[sprite.y, sprite.tile, sprite.attributes, sprite.x]);
+        }
+
+        bytes
+    }
+
+    // Lower indices are drawn on top of higher ones, so the first match is the visible one
+    pub fn get_sprite_at(&self, x: usize, y: usize, tall: bool) -> Option<usize> {
+        self.sprites.iter().position(|sprite| sprite.contains(x, y, tall))
+    }
+
+    pub fn get_free_sprite(&self) -> Option<usize> {
+        self.sprites.iter().position(|sprite| sprite.is_hidden())
+    }
+
+    // The sprites on every scanline in OAM order, which is the order the PPU evaluates them in
+    pub fn get_scanlines(&self, tall: bool) -> Vec<Vec<usize>> {
+        let mut scanlines = vec![vec![]; 240];
+
+        for (which, sprite) in self.sprites.iter().enumerate().filter(|(_, x)| !x.is_hidden()) {
+            for y in sprite.get_top()..(sprite.get_top() + get_height(tall)).min(240) {
+                scanlines[y].push(which);
+            }
+        }
+
+        scanlines
+    }
+
+    // Scanlines over the limit along with the sprites that drop out of them
+    pub fn get_overflow(&self, tall: bool) -> Vec<(usize, Vec<usize>)> {
+        self.get_scanlines(tall).into_iter().enumerate()
+            .filter(|(_, x)| x.len() > SPRITES_PER_SCANLINE)
+            .map(|(y, x)| (y, x[SPRITES_PER_SCANLINE..].to_vec()))
+            .collect()
+    }
+}
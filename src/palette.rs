@@ -5,6 +5,8 @@ use cgmath::{
     Vector3,
 };
 
+use crate::media;
+use crate::render;
 use crate::surface::Surface;
 use crate::vertex::Vertex;
 
@@ -14,6 +16,7 @@ use std::{
         Send,
         Sync,
     },
+    path::Path,
     sync::Arc,
 };
 
@@ -72,12 +75,15 @@ type PaletteDescriptorSet = Arc<
 >;
 
 pub struct Palette {
+    pub colors: [u8; 192],
+    pub grayscale: bool,
+    pub block_0d: bool,
     pub surface: Surface,
     pub vertex_shader: vs::Shader,
     pub fragment_shader: fs::Shader,
     pub pipeline: PaletteGraphicsPipeline,
     pub texture: Arc<ImmutableImage<Format>>,
-    pub tex_future: CommandBufferExecFuture<NowFuture, AutoCommandBuffer>,
+    pub tex_future: Option<CommandBufferExecFuture<NowFuture, AutoCommandBuffer>>,
     pub descriptor_set: PaletteDescriptorSet,
 }
 
@@ -88,25 +94,62 @@ impl Palette {
         render_pass: Arc<RenderPassAbstract + Send + Sync>,
         sampler: Arc<Sampler>,
     ) -> Self {
+        let colors = FULL_PALETTE;
+        let grayscale = false;
+        let block_0d = false;
         let surface = Self::get_surface(device.clone());
         let vertex_shader = vs::Shader::load(device.clone()).expect("Failed to create vertex shader");
         let fragment_shader = fs::Shader::load(device.clone()).expect("Failed to create fragment shader");
         let pipeline = Self::get_pipeline(device.clone(), &vertex_shader, &fragment_shader, render_pass.clone());
 
-        let (texture, tex_future) = Self::get_texture_and_future(queue.clone());
+        let (texture, tex_future) = Self::get_texture_and_future(queue.clone(), &colors, grayscale);
         let descriptor_set = Self::get_descriptor_set(pipeline.clone(), texture.clone(), sampler.clone());
 
         Self {
+            colors,
+            grayscale,
+            block_0d,
             surface,
             vertex_shader,
             fragment_shader,
             pipeline,
             texture,
-            tex_future,
+            tex_future: Some(tex_future),
             descriptor_set,
         }
     }
 
+    // Keeps the colors it has when the file cannot be loaded
+    pub fn load_from_file(self, path: &Path, queue: Arc<Queue>, sampler: Arc<Sampler>) -> Self {
+        let colors = match media::load_palette(path) {
+            Ok(result) => result,
+            Err(e) => {
+                println!("Failed to load colors for palette: {:?}", e);
+                return self;
+            }
+        };
+
+        let (texture, tex_future) = Self::get_texture_and_future(queue.clone(), &colors, self.grayscale);
+        let descriptor_set = Self::get_descriptor_set(self.pipeline.clone(), texture.clone(), sampler.clone());
+
+        Self {
+            colors,
+            texture,
+            tex_future: Some(tex_future),
+            descriptor_set,
+            ..self
+        }
+    }
+
+    pub fn set_grayscale(&mut self, grayscale: bool, queue: Arc<Queue>, sampler: Arc<Sampler>) {
+        let (texture, tex_future) = Self::get_texture_and_future(queue.clone(), &self.colors, grayscale);
+
+        self.descriptor_set = Self::get_descriptor_set(self.pipeline.clone(), texture.clone(), sampler.clone());
+        self.grayscale = grayscale;
+        self.texture = texture;
+        self.tex_future = Some(tex_future);
+    }
+
     pub fn set_position(self, position: Vector3<f32>) -> Self {
         let surface = Surface {
             position,
@@ -142,11 +185,11 @@ impl Palette {
         )
     }
 
-    fn get_texture_and_future(queue: Arc<Queue>) -> (
+    fn get_texture_and_future(queue: Arc<Queue>, colors: &[u8; 192], grayscale: bool) -> (
         Arc<ImmutableImage<Format>>, CommandBufferExecFuture<NowFuture, AutoCommandBuffer>
     ) {
-        let image_data: Vec<u8> = FULL_PALETTE.chunks(3).flat_map(
-            |x| vec![x[0], x[1], x[2], 255u8]
+        let image_data: Vec<u8> = (0..64).flat_map(
+            |x| render::get_flagged_color(x, get_masked_color_index(x, grayscale), colors).to_vec()
         ).collect();
 
         ImmutableImage::from_iter(
@@ -170,6 +213,44 @@ impl Palette {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorWarning {
+    DisruptsSync,
+    DuplicateBlack,
+    DuplicateWhite,
+}
+
+// $0D can upset the sync of some televisions, while $1D and the $xE/$xF columns only repeat black
+pub fn is_black(color_index: u8) -> bool {
+    (color_index & 0x0F == 0x0D && color_index < 0x20) || color_index & 0x0F >= 0x0E
+}
+
+// $0D disrupts the sync, the other blacks repeat $0F and $20 repeats $30
+pub fn get_color_warning(color_index: u8) -> Option<ColorWarning> {
+    if color_index == 0x0D {
+        Some(ColorWarning::DisruptsSync)
+    }
+    else if is_black(color_index) && color_index != 0x0F {
+        Some(ColorWarning::DuplicateBlack)
+    }
+    else if color_index == 0x20 {
+        Some(ColorWarning::DuplicateWhite)
+    }
+    else {
+        None
+    }
+}
+
+// With the PPUMASK grayscale bit set the PPU only reads the $x0 column
+pub fn get_masked_color_index(color_index: u8, grayscale: bool) -> u8 {
+    if grayscale {
+        color_index & 0x30
+    }
+    else {
+        color_index
+    }
+}
+
 pub mod vs {
     vulkano_shaders::shader!{
     ty: "vertex",
@@ -221,15 +302,27 @@ vec2 color_center = vec2(
 );
 
 void main() {
+    vec4 texel;
+
     if( abs(uv.x - color_center.x) < color_square_size.x / 1.5
      && abs(uv.y - color_center.y) < color_square_size.y / 1.5
       )
     {
-        color = vec4(texture(tex, color_center).xyz, 1.0);
+        texel = texture(tex, color_center);
     }
     else
     {
-        color = vec4(texture(tex, uv).xyz, 1.0);
+        texel = texture(tex, uv);
+    }
+
+    color = vec4(texel.xyz, 1.0);
+
+    // Problematic entries are flagged through the alpha channel
+    vec2 local = fract(uv * total_size);
+
+    if(texel.a < 1.0 && local.x + local.y < 0.4)
+    {
+        color = vec4(1.0, 0.0, 0.0, 1.0);
     }
 }
 "
@@ -5,6 +5,11 @@ use cgmath::{
     Vector3,
 };
 
+use crate::chr::{
+    self,
+    FillMode,
+    Transform,
+};
 use crate::media;
 use crate::surface::Surface;
 use crate::vertex::Vertex;
@@ -73,15 +78,27 @@ type PatternTableDescriptorSet = Arc<
     >
 >;
 
+// Overlay color that is brighter than any of the four colors, used for outlines
+pub const HIGHLIGHT: u8 = 4;
+
+// Bits of the second texture channel, 85 and 170 combine into 255 for a transparent pixel of a marked tile
+const TRANSPARENT_FLAG: u8 = 85;
+const MARKED_FLAG: u8 = 170;
+
 pub struct PatternTable {
     pub bytes: [u8; 8192],
     pub pixels: [u8; 32768],
+    pub overlay: Vec<(usize, usize, u8)>,
+    pub marked: Vec<usize>,
+    pub tall: bool,
+    pub checkerboard: bool,
+    pub sprite_banks: [bool; 2],
     pub surface: Surface,
     pub vertex_shader: vs::Shader,
     pub fragment_shader: fs::Shader,
     pub pipeline: PatternTableGraphicsPipeline,
     pub texture: Arc<ImmutableImage<Format>>,
-    pub tex_future: CommandBufferExecFuture<NowFuture, AutoCommandBuffer>,
+    pub tex_future: Option<CommandBufferExecFuture<NowFuture, AutoCommandBuffer>>,
     pub descriptor_set: PatternTableDescriptorSet,
 }
 
@@ -100,18 +117,23 @@ impl PatternTable {
         let pipeline = Self::get_pipeline(device.clone(), &vertex_shader, &fragment_shader, render_pass.clone());
 
         // Arguably redundant
-        let (texture, tex_future) = Self::get_texture_and_future(queue.clone(), &pixels);
+        let (texture, tex_future) = Self::get_texture_and_future(queue.clone(), &pixels, &[], false, None);
         let descriptor_set = Self::get_descriptor_set(pipeline.clone(), texture.clone(), sampler.clone());
 
         Self {
             bytes,
             pixels,
+            overlay: vec![],
+            marked: vec![],
+            tall: false,
+            checkerboard: false,
+            sprite_banks: [false, true],
             surface,
             vertex_shader,
             fragment_shader,
             pipeline,
             texture,
-            tex_future,
+            tex_future: Some(tex_future),
             descriptor_set,
         }
     }
@@ -122,19 +144,119 @@ impl PatternTable {
             Err(_) => panic!("Failed to load bytes and pixels for pattern table!")
         };
 
-        let (texture, tex_future) = Self::get_texture_and_future(queue.clone(), &pixels);
+        let (texture, tex_future) = Self::get_texture_and_future(
+            queue.clone(), &pixels, &self.marked, self.tall, self.get_transparent_banks()
+        );
         let descriptor_set = Self::get_descriptor_set(self.pipeline.clone(), texture.clone(), sampler.clone());
 
         Self {
             bytes,
             pixels,
             texture,
-            tex_future,
+            tex_future: Some(tex_future),
             descriptor_set,
             ..self
         }
     }
 
+    // Points outside of the sheet are ignored, returns the changed pixels with their previous and new color
+    pub fn draw(&mut self, points: &[(i32, i32)], color: u8) -> Vec<(usize, usize, u8, u8)> {
+        let mut changes = vec![];
+
+        for &(x, y) in points {
+            if chr::is_on_sheet(x, y) {
+                let (x, y) = (x as usize, y as usize);
+                let previous = chr::set_pixel(&mut self.pixels, &mut self.bytes, x, y, color);
+
+                if previous != color {
+                    changes.push((x, y, previous, color));
+                }
+            }
+        }
+
+        changes
+    }
+
+    // Sets every pixel to its own color, returns the changes like draw does
+    pub fn set_pixels(&mut self, pixels: &[(usize, usize, u8)]) -> Vec<(usize, usize, u8, u8)> {
+        let mut changes = vec![];
+
+        for &(x, y, color) in pixels {
+            let previous = chr::set_pixel(&mut self.pixels, &mut self.bytes, x, y, color);
+
+            if previous != color {
+                changes.push((x, y, previous, color));
+            }
+        }
+
+        changes
+    }
+
+    // The region is given in pixels, returns the changes like draw does
+    pub fn transform(&mut self, region: (usize, usize, usize, usize), transform: Transform) -> Vec<(usize, usize, u8, u8)> {
+        let pixels = chr::get_transformed(&self.pixels, region, transform);
+
+        self.set_pixels(&pixels)
+    }
+
+    pub fn fill(&mut self, x: usize, y: usize, color: u8, mode: FillMode) -> Vec<(usize, usize, u8, u8)> {
+        let previous = self.pixels[y * chr::SHEET_WIDTH + x];
+
+        chr::flood_fill(&mut self.pixels, &mut self.bytes, x, y, color, mode).iter()
+            .map(|&(x, y)| (x, y, previous, color))
+            .collect()
+    }
+
+    // The overlay is shown on top of the pixels without being committed to them
+    pub fn update_texture(&mut self, overlay: &[(usize, usize, u8)], queue: Arc<Queue>, sampler: Arc<Sampler>) {
+        self.overlay = overlay.to_vec();
+        self.refresh_texture(queue, sampler);
+    }
+
+    // Marked tiles are tinted, the texture is only refreshed when they change
+    pub fn set_marked(&mut self, marked: Vec<usize>, queue: Arc<Queue>, sampler: Arc<Sampler>) {
+        if marked != self.marked {
+            self.marked = marked;
+            self.refresh_texture(queue, sampler);
+        }
+    }
+
+    // Switches the view between the regular and the 8x16 arrangement of the tiles
+    pub fn set_tall(&mut self, tall: bool, queue: Arc<Queue>, sampler: Arc<Sampler>) {
+        self.tall = tall;
+        self.refresh_texture(queue, sampler);
+    }
+
+    // Color 0 is drawn as a checkerboard on banks used for sprites, where it is transparent
+    pub fn set_transparency(&mut self, checkerboard: bool, sprite_banks: [bool; 2], queue: Arc<Queue>, sampler: Arc<Sampler>) {
+        if checkerboard != self.checkerboard || sprite_banks != self.sprite_banks {
+            self.checkerboard = checkerboard;
+            self.sprite_banks = sprite_banks;
+            self.refresh_texture(queue, sampler);
+        }
+    }
+
+    // The banks that show color 0 as transparent, if the checkerboard is shown at all
+    pub fn get_transparent_banks(&self) -> Option<[bool; 2]> {
+        if self.checkerboard { Some(self.sprite_banks) } else { None }
+    }
+
+    fn refresh_texture(&mut self, queue: Arc<Queue>, sampler: Arc<Sampler>) {
+        let mut pixels = self.pixels;
+
+        for &(x, y, color) in self.overlay.iter() {
+            pixels[y * chr::SHEET_WIDTH + x] = color;
+        }
+
+        let (texture, tex_future) = Self::get_texture_and_future(
+            queue.clone(), &pixels, &self.marked, self.tall, self.get_transparent_banks()
+        );
+
+        self.descriptor_set = Self::get_descriptor_set(self.pipeline.clone(), texture.clone(), sampler.clone());
+        self.texture = texture;
+        self.tex_future = Some(tex_future);
+    }
+
     // pub fn set_surface(self, surface: Surface) {
     //     Self {
     //         surface,
@@ -165,21 +287,48 @@ impl PatternTable {
         )
     }
 
-    fn get_texture_and_future(queue: Arc<Queue>, pixels: &[u8; 32768]) -> (
+    // The second channel holds flags, one third for a transparent pixel and two thirds for a marked tile
+    // The first channel only holds colors, the highlight already fills it completely
+    fn get_texture_and_future(
+        queue: Arc<Queue>,
+        pixels: &[u8; 32768],
+        marked: &[usize],
+        tall: bool,
+        transparent_banks: Option<[bool; 2]>
+    ) -> (
         Arc<ImmutableImage<Format>>, CommandBufferExecFuture<NowFuture, AutoCommandBuffer>
     ) {
-        let mut image_data: [u8; 32768] = [0u8; 32768];
+        let mut sheet_data: Vec<u8> = vec![0u8; 65536];
         
         for (i, x) in pixels.iter().enumerate() {
-            let pixel: u8 = (*x as f32 * (255.0 / 4.0)) as u8;
+            let bank = i % chr::SHEET_WIDTH / 128;
+            let is_transparent = *x == 0 && transparent_banks.map_or(false, |x| x[bank]);
 
-            image_data[i] = pixel;
+            sheet_data[i * 2] = (*x as f32 * (255.0 / 4.0)) as u8;
+            sheet_data[i * 2 + 1] = if is_transparent { TRANSPARENT_FLAG } else { 0 };
+        }
+
+        for tile in marked {
+            let (origin_x, origin_y) = chr::get_tile_origin(*tile);
+
+            for i in 0..64 {
+                sheet_data[((origin_y + i / 8) * chr::SHEET_WIDTH + origin_x + i % 8) * 2 + 1] |= MARKED_FLAG;
+            }
+        }
+
+        let mut image_data: Vec<u8> = vec![0u8; 65536];
+
+        for i in 0..32768 {
+            let (x, y) = chr::get_sheet_position(i % chr::SHEET_WIDTH, i / chr::SHEET_WIDTH, tall);
+            let index = (y * chr::SHEET_WIDTH + x) * 2;
+
+            image_data[i * 2..i * 2 + 2].copy_from_slice(&sheet_data[index..index + 2]);
         }
 
         ImmutableImage::from_iter(
             image_data.iter().cloned(),
             Dimensions::Dim2d { width: 256, height: 128 },
-            Format::R8Unorm,
+            Format::R8G8Unorm,
             queue.clone()
         ).unwrap()
     }
@@ -241,7 +390,21 @@ layout(location = 0) out vec4 color;
 
 void main() {
     color = mouse.xxxx; // dummy
-    color = vec4(texture(tex, uv).xxx, 1.0);
+    vec4 texel = texture(tex, uv);
+
+    color = vec4(texel.xxx, 1.0);
+
+    int flags = int(round(texel.y * 3.0));
+
+    // Squares of four pixels
+    if ((flags & 1) != 0) {
+        vec2 cell = floor(uv * vec2(64.0, 32.0));
+        color.rgb = mod(cell.x + cell.y, 2.0) < 1.0 ? vec3(0.6, 0.5, 0.65) : vec3(0.45, 0.35, 0.5);
+    }
+
+    if ((flags & 2) != 0) {
+        color.rgb = mix(color.rgb, vec3(1.0, 0.0, 0.0), 0.35);
+    }
 }
 "
     }
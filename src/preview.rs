@@ -0,0 +1,234 @@
+// Copyright 2019, Sjors van Gelderen
+
+use cgmath::{
+    Vector2,
+    Vector3,
+};
+
+use crate::surface::Surface;
+use crate::vertex::Vertex;
+
+use std::{
+    boxed::Box,
+    marker::{
+        Send,
+        Sync,
+    },
+    sync::Arc,
+};
+
+use vulkano::{
+    command_buffer::{
+        AutoCommandBuffer,
+        CommandBufferExecFuture,
+    },
+    descriptor::{
+        descriptor_set::{
+            PersistentDescriptorSet,
+            PersistentDescriptorSetImg,
+            PersistentDescriptorSetSampler,
+        },
+        PipelineLayoutAbstract,
+    },
+    device::{
+        Device,
+        Queue,
+    },
+    format::Format,
+    framebuffer::{
+        RenderPassAbstract,
+        Subpass,
+    },
+    image::{
+        Dimensions,
+        ImmutableImage,
+    },
+    pipeline::{
+        GraphicsPipeline,
+        vertex::{
+            SingleBufferDefinition,
+        }
+    },
+    sampler::Sampler,
+    sync::NowFuture,
+};
+
+type PreviewGraphicsPipeline = Arc<
+    GraphicsPipeline<
+        SingleBufferDefinition<Vertex>,
+        Box<(dyn PipelineLayoutAbstract + Sync + Send + 'static)>,
+        Arc<(dyn RenderPassAbstract + Sync + Send + 'static)>
+    >
+>;
+
+type PreviewDescriptorSet = Arc<
+    PersistentDescriptorSet<
+        PreviewGraphicsPipeline,
+        (
+            ((), PersistentDescriptorSetImg<Arc<ImmutableImage<Format>>>),
+            PersistentDescriptorSetSampler
+        )
+    >
+>;
+
+// Shows RGBA data produced by the software renderer
+pub struct Preview {
+    pub image_dimensions: Vector2<u32>,
+    pub surface: Surface,
+    pub vertex_shader: vs::Shader,
+    pub fragment_shader: fs::Shader,
+    pub pipeline: PreviewGraphicsPipeline,
+    pub texture: Arc<ImmutableImage<Format>>,
+    pub tex_future: Option<CommandBufferExecFuture<NowFuture, AutoCommandBuffer>>,
+    pub descriptor_set: PreviewDescriptorSet,
+}
+
+impl Preview {
+    pub fn new(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        render_pass: Arc<RenderPassAbstract + Send + Sync>,
+        sampler: Arc<Sampler>,
+        image_dimensions: Vector2<u32>,
+        surface_dimensions: Vector2<f32>,
+    ) -> Self {
+        let image_data = vec![0u8; (image_dimensions.x * image_dimensions.y * 4) as usize];
+        let surface = Surface::new(device.clone(), Vector3::new(0.0, 0.0, 1.0), surface_dimensions);
+        let vertex_shader = vs::Shader::load(device.clone()).expect("Failed to create vertex shader");
+        let fragment_shader = fs::Shader::load(device.clone()).expect("Failed to create fragment shader");
+        let pipeline = Self::get_pipeline(device.clone(), &vertex_shader, &fragment_shader, render_pass.clone());
+
+        let (texture, tex_future) = Self::get_texture_and_future(queue.clone(), image_dimensions, &image_data);
+        let descriptor_set = Self::get_descriptor_set(pipeline.clone(), texture.clone(), sampler.clone());
+
+        Self {
+            image_dimensions,
+            surface,
+            vertex_shader,
+            fragment_shader,
+            pipeline,
+            texture,
+            tex_future: Some(tex_future),
+            descriptor_set,
+        }
+    }
+
+    pub fn set_position(self, position: Vector3<f32>) -> Self {
+        let surface = Surface {
+            position,
+            ..self.surface
+        };
+
+        Self {
+            surface,
+            ..self
+        }
+    }
+
+    pub fn update_image(
+        &mut self,
+        image_dimensions: Vector2<u32>,
+        image_data: &[u8],
+        queue: Arc<Queue>,
+        sampler: Arc<Sampler>
+    ) {
+        let (texture, tex_future) = Self::get_texture_and_future(queue.clone(), image_dimensions, image_data);
+
+        self.descriptor_set = Self::get_descriptor_set(self.pipeline.clone(), texture.clone(), sampler.clone());
+        self.image_dimensions = image_dimensions;
+        self.texture = texture;
+        self.tex_future = Some(tex_future);
+    }
+
+    fn get_pipeline(
+        device: Arc<Device>,
+        vertex_shader: &vs::Shader,
+        fragment_shader: &fs::Shader,
+        render_pass: Arc<RenderPassAbstract + Send + Sync>
+    ) -> PreviewGraphicsPipeline {
+        Arc::new(
+            GraphicsPipeline::start()
+                .vertex_input_single_buffer()
+                .vertex_shader(vertex_shader.main_entry_point(), ())
+                .triangle_list()
+                .viewports_dynamic_scissors_irrelevant(1)
+                .fragment_shader(fragment_shader.main_entry_point(), ())
+                .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+                .build(device.clone())
+                .unwrap()
+        )
+    }
+
+    fn get_texture_and_future(queue: Arc<Queue>, image_dimensions: Vector2<u32>, image_data: &[u8]) -> (
+        Arc<ImmutableImage<Format>>, CommandBufferExecFuture<NowFuture, AutoCommandBuffer>
+    ) {
+        ImmutableImage::from_iter(
+            image_data.iter().cloned(),
+            Dimensions::Dim2d { width: image_dimensions.x, height: image_dimensions.y },
+            Format::R8G8B8A8Unorm,
+            queue.clone()
+        ).unwrap()
+    }
+
+    fn get_descriptor_set(
+        pipeline: PreviewGraphicsPipeline,
+        texture: Arc<ImmutableImage<Format>>,
+        sampler: Arc<Sampler>
+    ) -> PreviewDescriptorSet {
+        Arc::new(
+            PersistentDescriptorSet::start(pipeline.clone(), 0)
+            .add_sampled_image(texture.clone(), sampler.clone()).unwrap()
+            .build().unwrap()
+        )
+    }
+}
+
+pub mod vs {
+    vulkano_shaders::shader!{
+    ty: "vertex",
+    src:
+"
+#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec2 uv;
+
+layout(push_constant) uniform UBO {
+    mat4 mvp;
+    vec2 mouse;
+} ubo;
+
+layout(location = 0) out vec2 uv_out;
+layout(location = 1) out vec2 mouse_out;
+
+void main() {
+    gl_Position = ubo.mvp * vec4(position, 1.0);
+
+    uv_out = uv;
+    mouse_out = ubo.mouse;
+}
+"
+    }
+}
+
+pub mod fs {
+    vulkano_shaders::shader!{
+        ty: "fragment",
+        src:
+"
+#version 450
+
+layout(location = 0) in vec2 uv;
+layout(location = 1) in vec2 mouse;
+
+layout(set = 0, binding = 0) uniform sampler2D tex;
+
+layout(location = 0) out vec4 color;
+
+void main() {
+    color = mouse.xxxx; // dummy
+    color = vec4(texture(tex, uv).xyz, 1.0);
+}
+"
+    }
+}
@@ -0,0 +1,212 @@
+// Copyright 2019, Sjors van Gelderen
+
+use crate::animation::{
+    Animation,
+    Cel,
+};
+use crate::history::Edit;
+use crate::metasprite::Metasprite;
+use crate::nametable::{
+    self,
+    Nametable,
+};
+use crate::oam::{
+    self,
+    Oam,
+    Sprite,
+};
+
+// Everything that is edited besides the character data and the samples
+pub struct Project {
+    pub nametables: Vec<Nametable>,
+    pub background_page: usize,
+    pub oam: Oam,
+    pub sprite_page: usize,
+    pub tall_sprites: bool,
+    pub metasprites: Vec<Metasprite>,
+    pub animations: Vec<Animation>,
+}
+
+impl Project {
+    pub fn new() -> Self {
+        Self {
+            nametables: vec![Nametable::zero()],
+            background_page: 0,
+            oam: Oam::new(),
+            sprite_page: 1,
+            tall_sprites: false,
+            metasprites: vec![],
+            animations: vec![],
+        }
+    }
+
+    // Updates every reference to tiles on the page after they are rearranged
+    pub fn remap_tiles(&mut self, page: usize, mapping: &[u8; 256]) -> Vec<Edit> {
+        let mut edits = vec![];
+
+        if page == self.background_page {
+            for (i, nametable) in self.nametables.iter_mut().enumerate() {
+                edits.extend(nametable.remap_tiles(mapping).into_iter().map(|(x, y, from, to)| {
+                    Edit::Tile { nametable: i, x, y, from, to }
+                }));
+            }
+        }
+
+        let (sprite_page, tall) = (self.sprite_page, self.tall_sprites);
+
+        for (which, sprite) in self.oam.sprites.iter_mut().enumerate() {
+            let from = *sprite;
+            sprite.tile = get_remapped_sprite_tile(sprite.tile, page, mapping, sprite_page, tall);
+
+            if *sprite != from {
+                edits.push(Edit::Sprite { which, from, to: *sprite });
+            }
+        }
+
+        for (which, metasprite) in self.metasprites.iter_mut().enumerate() {
+            let from = metasprite.clone();
+
+            for part in metasprite.parts.iter_mut() {
+                part.tile = get_remapped_sprite_tile(part.tile, page, mapping, sprite_page, tall);
+            }
+
+            if *metasprite != from {
+                edits.push(Edit::Metasprite { which, from, to: metasprite.clone() });
+            }
+        }
+
+        for (which, animation) in self.animations.iter_mut().enumerate() {
+            let from = animation.clone();
+
+            for frame in animation.frames.iter_mut() {
+                if let Cel::Tile { ref mut tile, .. } = frame.cel {
+                    *tile = get_remapped_sprite_tile(*tile, page, mapping, sprite_page, tall);
+                }
+            }
+
+            if *animation != from {
+                edits.push(Edit::Animation { which, from, to: animation.clone() });
+            }
+        }
+
+        edits
+    }
+
+    // Counts the references to every tile on the page
+    pub fn get_tile_usage(&self, page: usize) -> [usize; 256] {
+        let mut usage = [0usize; 256];
+
+        if page == self.background_page {
+            for nametable in self.nametables.iter() {
+                for tile in nametable.tiles.iter() {
+                    usage[*tile as usize] += 1;
+                }
+            }
+        }
+
+        let sprites = self.get_sprite_references();
+
+        for (sprite_page, tile) in sprites.iter().flat_map(|x| x.get_tiles(self.sprite_page, self.tall_sprites)) {
+            if sprite_page == page {
+                usage[tile as usize] += 1;
+            }
+        }
+
+        usage
+    }
+
+    // Tiles of 8x16 pairs in use that the mapping splits up, the sprites showing them cannot follow
+    pub fn get_split_pairs(&self, page: usize, mapping: &[u8; 256]) -> Vec<usize> {
+        let mut split = vec![];
+
+        if !self.tall_sprites {
+            return split;
+        }
+
+        for sprite in self.get_sprite_references() {
+            let top = (sprite.tile & 0xFE) as usize;
+            let moved = mapping[top] as usize != top || mapping[top + 1] as usize != top + 1;
+            let follows = get_remapped_sprite_tile(sprite.tile, page, mapping, self.sprite_page, true) != sprite.tile;
+
+            if (sprite.tile & 1) as usize == page && moved && !follows && !split.contains(&top) {
+                split.push(top);
+                split.push(top + 1);
+            }
+        }
+
+        split
+    }
+
+    pub fn get_unused_tiles(&self, page: usize) -> Vec<u8> {
+        self.get_tile_usage(page).iter().enumerate()
+            .filter(|(_, x)| **x == 0)
+            .map(|(i, _)| i as u8)
+            .collect()
+    }
+
+    // The first pixel in rendering order where sprite 0 and the background are both opaque
+    // Assumes the first nametable without scrolling and the left column shown, the hit never occurs at x 255
+    pub fn get_sprite_zero_hit(&self, pixels: &[u8; 32768]) -> Option<(usize, usize)> {
+        let sprite = self.oam.sprites[0];
+
+        if sprite.is_hidden() {
+            return None;
+        }
+
+        for y in 0..oam::get_height(self.tall_sprites) {
+            for x in 0..8 {
+                let (screen_x, screen_y) = (sprite.x as usize + x, sprite.get_top() + y);
+
+                if screen_x >= 255 || screen_y >= nametable::HEIGHT * 8 {
+                    continue;
+                }
+
+                let sprite_pixel = sprite.get_pixel(pixels, self.sprite_page, x, y, self.tall_sprites);
+                let background_pixel = self.nametables[0].get_pixel(pixels, self.background_page, screen_x, screen_y);
+
+                if sprite_pixel != 0 && background_pixel != 0 {
+                    return Some((screen_x, screen_y));
+                }
+            }
+        }
+
+        None
+    }
+
+    // The shown sprites along with the metasprite parts and tile cels, as sprites
+    fn get_sprite_references(&self) -> Vec<Sprite> {
+        let parts = self.metasprites.iter()
+            .flat_map(|x| x.parts.iter())
+            .map(|x| Sprite { tile: x.tile, attributes: x.attributes, ..Sprite::hidden() });
+
+        let cels = self.animations.iter()
+            .flat_map(|x| x.frames.iter())
+            .filter_map(|x| match x.cel {
+                Cel::Tile { tile, attributes } => Some(Sprite { tile, attributes, ..Sprite::hidden() }),
+                _ => None,
+            });
+
+        self.oam.sprites.iter()
+            .filter(|x| !x.is_hidden())
+            .cloned()
+            .chain(parts)
+            .chain(cels)
+            .collect()
+    }
+}
+
+// Tall sprites only follow when both tiles of their pair stay together
+fn get_remapped_sprite_tile(tile: u8, page: usize, mapping: &[u8; 256], sprite_page: usize, tall: bool) -> u8 {
+    if !tall {
+        return if page == sprite_page { mapping[tile as usize] } else { tile };
+    }
+
+    let top = mapping[(tile & 0xFE) as usize];
+
+    if (tile & 1) as usize == page && top % 2 == 0 && mapping[(tile | 1) as usize] == top + 1 {
+        top | (tile & 1)
+    }
+    else {
+        tile
+    }
+}
@@ -0,0 +1,246 @@
+// Copyright 2019, Sjors van Gelderen
+
+// Software renders produce RGBA data for previews and exported images
+
+use crate::chr;
+use crate::nametable::{
+    self,
+    Nametable,
+};
+use crate::oam::{
+    self,
+    Oam,
+};
+use crate::palette;
+use crate::samples;
+
+pub fn get_color(color_index: u8, colors: &[u8; 192]) -> [u8; 4] {
+    let index = color_index as usize * 3;
+
+    [colors[index], colors[index + 1], colors[index + 2], 255u8]
+}
+
+// Colors with a palette warning get half alpha so the views can mark them
+pub fn get_flagged_color(color_index: u8, shown_color_index: u8, colors: &[u8; 192]) -> [u8; 4] {
+    let mut color = get_color(shown_color_index, colors);
+
+    if palette::get_color_warning(color_index).is_some() {
+        color[3] = 128u8;
+    }
+
+    color
+}
+
+// Lays out every set of samples as one row of both sample rows side by side
+pub fn render_samples(steps: &[[u8; 26]], colors: &[u8; 192], grayscale: bool) -> Vec<u8> {
+    steps.iter()
+        .flat_map(|x| x.iter())
+        .flat_map(|x| get_color(palette::get_masked_color_index(*x, grayscale), colors).to_vec())
+        .collect()
+}
+
+// Renders both pages side by side with a single sub-palette
+// Color 0 becomes a checkerboard on the pages that are given as transparent
+pub fn render_pattern_table(
+    pixels: &[u8; 32768],
+    sub_palette: [u8; 4],
+    colors: &[u8; 192],
+    grayscale: bool,
+    transparent_banks: Option<[bool; 2]>
+) -> Vec<u8> {
+    let sub_palette_colors: Vec<[u8; 4]> = sub_palette.iter()
+        .map(|x| get_color(palette::get_masked_color_index(*x, grayscale), colors))
+        .collect();
+
+    pixels.iter().enumerate().flat_map(|(i, x)| {
+        let (x0, y0) = (i % chr::SHEET_WIDTH, i / chr::SHEET_WIDTH);
+
+        if *x == 0 && transparent_banks.map_or(false, |banks| banks[x0 / 128]) {
+            get_checker_color(x0, y0).to_vec()
+        }
+        else {
+            sub_palette_colors[*x as usize].to_vec()
+        }
+    }).collect()
+}
+
+// Renders a full screen of 256 by 240 pixels
+pub fn render_nametable(
+    nametable: &Nametable,
+    pixels: &[u8; 32768],
+    page: usize,
+    color_indices: &[u8; 26],
+    colors: &[u8; 192],
+    grayscale: bool
+) -> Vec<u8> {
+    let width = nametable::WIDTH * 8;
+    let mut image_data = vec![0u8; width * nametable::HEIGHT * 8 * 4];
+
+    for tile_y in 0..nametable::HEIGHT {
+        for tile_x in 0..nametable::WIDTH {
+            let tile = page * 256 + nametable.get_tile(tile_x, tile_y) as usize;
+            let (origin_x, origin_y) = chr::get_tile_origin(tile);
+            let sub_palette = samples::get_sub_palette(
+                color_indices,
+                nametable.attribute_table.get_sub_palette(tile_x, tile_y) as usize
+            );
+
+            for y in 0..8 {
+                for x in 0..8 {
+                    let pixel = pixels[(origin_y + y) * chr::SHEET_WIDTH + origin_x + x];
+                    let color = get_color(palette::get_masked_color_index(sub_palette[pixel as usize], grayscale), colors);
+                    let index = ((tile_y * 8 + y) * width + tile_x * 8 + x) * 4;
+
+                    image_data[index..index + 4].copy_from_slice(&color);
+                }
+            }
+        }
+    }
+
+    image_data
+}
+
+// Renders the sprites over the universal background color or a checkerboard, with lower indices in front of higher ones
+// With the limit applied, sprites past the eighth on a scanline are left out like the PPU does
+pub fn render_sprites(
+    oam: &Oam,
+    pixels: &[u8; 32768],
+    page: usize,
+    tall: bool,
+    limit: bool,
+    checkerboard: bool,
+    color_indices: &[u8; 26],
+    colors: &[u8; 192],
+    grayscale: bool
+) -> Vec<u8> {
+    let (width, height) = (nametable::WIDTH * 8, nametable::HEIGHT * 8);
+    let background = get_color(palette::get_masked_color_index(color_indices[0], grayscale), colors);
+    let mut image_data: Vec<u8> = (0..width * height).flat_map(|i| {
+        if checkerboard { get_checker_color(i % width, i / width) } else { background }.to_vec()
+    }).collect();
+    let scanlines = oam.get_scanlines(tall);
+
+    for (which, sprite) in oam.sprites.iter().enumerate().rev().filter(|(_, x)| !x.is_hidden()) {
+        let sub_palette = samples::get_sub_palette(color_indices, 4 + sprite.get_sub_palette() as usize);
+
+        for y in 0..oam::get_height(tall) {
+            for x in 0..8 {
+                let (screen_x, screen_y) = (sprite.x as usize + x, sprite.get_top() + y);
+                let pixel = sprite.get_pixel(pixels, page, x, y, tall);
+
+                // Color 0 is transparent and sprites are cut off at the edges of the screen
+                if pixel == 0 || screen_x >= width || screen_y >= height {
+                    continue;
+                }
+
+                if limit && is_dropped(&scanlines, which, screen_y) {
+                    continue;
+                }
+
+                let color = get_color(palette::get_masked_color_index(sub_palette[pixel as usize], grayscale), colors);
+                let index = (screen_y * width + screen_x) * 4;
+
+                image_data[index..index + 4].copy_from_slice(&color);
+            }
+        }
+    }
+
+    image_data
+}
+
+// Renders the sprites over the background the way the PPU combines them
+// The lowest OAM index with an opaque pixel wins first, only then does its priority bit decide against the background
+// A sprite behind the background therefore still hides higher sprites wherever the background is opaque
+pub fn render_scene(
+    nametable: &Nametable,
+    oam: &Oam,
+    pixels: &[u8; 32768],
+    background_page: usize,
+    sprite_page: usize,
+    tall: bool,
+    limit: bool,
+    color_indices: &[u8; 26],
+    colors: &[u8; 192],
+    grayscale: bool
+) -> Vec<u8> {
+    let (width, height) = (nametable::WIDTH * 8, nametable::HEIGHT * 8);
+    let mut image_data = render_nametable(nametable, pixels, background_page, color_indices, colors, grayscale);
+    let mut covered = vec![false; width * height];
+    let scanlines = oam.get_scanlines(tall);
+
+    for (which, sprite) in oam.sprites.iter().enumerate().filter(|(_, x)| !x.is_hidden()) {
+        let sub_palette = samples::get_sub_palette(color_indices, 4 + sprite.get_sub_palette() as usize);
+        let behind = sprite.attributes & oam::BEHIND_BACKGROUND > 0;
+
+        for y in 0..oam::get_height(tall) {
+            for x in 0..8 {
+                let (screen_x, screen_y) = (sprite.x as usize + x, sprite.get_top() + y);
+                let pixel = sprite.get_pixel(pixels, sprite_page, x, y, tall);
+
+                if pixel == 0 || screen_x >= width || screen_y >= height {
+                    continue;
+                }
+
+                if (limit && is_dropped(&scanlines, which, screen_y)) || covered[screen_y * width + screen_x] {
+                    continue;
+                }
+
+                covered[screen_y * width + screen_x] = true;
+
+                // Background color 0 is transparent, so sprites behind it still show there
+                if behind && nametable.get_pixel(pixels, background_page, screen_x, screen_y) != 0 {
+                    continue;
+                }
+
+                let color = get_color(palette::get_masked_color_index(sub_palette[pixel as usize], grayscale), colors);
+                let index = (screen_y * width + screen_x) * 4;
+
+                image_data[index..index + 4].copy_from_slice(&color);
+            }
+        }
+    }
+
+    image_data
+}
+
+// Tints whole rows of RGBA data red, for example to mark scanlines
+pub fn render_marked_lines(image_data: &mut [u8], width: usize, lines: &[usize]) {
+    for y in lines {
+        for pixel in image_data[y * width * 4..(y + 1) * width * 4].chunks_mut(4) {
+            pixel[0] = (pixel[0] as u16 + (255 - pixel[0] as u16) / 2) as u8;
+            pixel[1] /= 2;
+            pixel[2] /= 2;
+        }
+    }
+}
+
+// Transparency is shown as squares of four pixels, in colors that stand apart from the grays of the pattern table
+pub fn get_checker_color(x: usize, y: usize) -> [u8; 4] {
+    if (x / 4 + y / 4) % 2 == 0 { [153, 128, 166, 255] } else { [115, 89, 128, 255] }
+}
+
+// Paints the given pixels of RGBA data in a single color
+pub fn render_colored_outline(image_data: &mut [u8], width: usize, outline: &[(usize, usize)], color: [u8; 4]) {
+    for &(x, y) in outline {
+        let index = (y * width + x) * 4;
+        image_data[index..index + 4].copy_from_slice(&color);
+    }
+}
+
+// Inverts the given pixels of RGBA data so they stand out on any color
+pub fn render_outline(image_data: &mut [u8], width: usize, outline: &[(usize, usize)]) {
+    for &(x, y) in outline {
+        let index = (y * width + x) * 4;
+
+        for channel in &mut image_data[index..index + 3] {
+            *channel = 255 - *channel;
+        }
+    }
+}
+
+// Whether the sprite is past the eighth on the scanline
+fn is_dropped(scanlines: &[Vec<usize>], which: usize, y: usize) -> bool {
+    scanlines[y].iter()
+        .position(|x| *x == which)
+        .map_or(false, |x| x >= oam::SPRITES_PER_SCANLINE)
+}
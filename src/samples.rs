@@ -5,7 +5,11 @@ use cgmath::{
     Vector3,
 };
 
-use crate::palette::FULL_PALETTE;
+use crate::palette::{
+    self,
+    ColorWarning,
+};
+use crate::render;
 use crate::surface::Surface;
 use crate::vertex::Vertex;
 
@@ -74,12 +78,13 @@ type SamplesDescriptorSet = Arc<
 
 pub struct Samples {
     pub color_indices: [u8; 26],
+    pub grayscale: bool,
     pub surface: Surface,
     pub vertex_shader: vs::Shader,
     pub fragment_shader: fs::Shader,
     pub pipeline: SamplesGraphicsPipeline,
     pub texture: Arc<ImmutableImage<Format>>,
-    pub tex_future: CommandBufferExecFuture<NowFuture, AutoCommandBuffer>,
+    pub tex_future: Option<CommandBufferExecFuture<NowFuture, AutoCommandBuffer>>,
     pub descriptor_set: SamplesDescriptorSet,
 }
 
@@ -89,28 +94,31 @@ impl Samples {
         queue: Arc<Queue>,
         render_pass: Arc<RenderPassAbstract + Send + Sync>,
         sampler: Arc<Sampler>,
+        colors: &[u8; 192],
     ) -> Self {
         let mut color_indices: [u8; 26] = [0; 26];
         for (i, x) in (0..26).enumerate() {
             color_indices[i] = x;
         }
 
+        let grayscale = false;
         let surface = Self::get_surface(device.clone());
         let vertex_shader = vs::Shader::load(device.clone()).expect("Failed to create vertex shader");
         let fragment_shader = fs::Shader::load(device.clone()).expect("Failed to create fragment shader");
         let pipeline = Self::get_pipeline(device.clone(), &vertex_shader, &fragment_shader, render_pass.clone());
 
-        let (texture, tex_future) = Self::get_texture_and_future(&color_indices, queue.clone());
+        let (texture, tex_future) = Self::get_texture_and_future(&color_indices, colors, grayscale, queue.clone());
         let descriptor_set = Self::get_descriptor_set(pipeline.clone(), texture.clone(), sampler.clone());
 
         Self {
             color_indices,
+            grayscale,
             surface,
             vertex_shader,
             fragment_shader,
             pipeline,
             texture,
-            tex_future,
+            tex_future: Some(tex_future),
             descriptor_set,
         }
     }
@@ -127,15 +135,58 @@ impl Samples {
         }
     }
 
-    // pub fn set_color_index(self, which: usize, to_color_index: u8) -> Self {
-    //     let mut color_indices = self.color_indices;
-    //     color_indices[which] = to_color_index;
+    pub fn set_color_indices(
+        &mut self,
+        color_indices: [u8; 26],
+        colors: &[u8; 192],
+        queue: Arc<Queue>,
+        sampler: Arc<Sampler>
+    ) {
+        let (texture, tex_future) = Self::get_texture_and_future(&color_indices, colors, self.grayscale, queue.clone());
+
+        self.descriptor_set = Self::get_descriptor_set(self.pipeline.clone(), texture.clone(), sampler.clone());
+        self.color_indices = color_indices;
+        self.texture = texture;
+        self.tex_future = Some(tex_future);
+    }
+
+    pub fn set_grayscale(&mut self, grayscale: bool, colors: &[u8; 192], queue: Arc<Queue>, sampler: Arc<Sampler>) {
+        self.grayscale = grayscale;
+
+        let color_indices = self.color_indices;
+        self.set_color_indices(color_indices, colors, queue, sampler);
+    }
+
+    // Blocked colors are rejected, other problematic colors are assigned and reported
+    pub fn set_color_index(
+        &mut self,
+        which: usize,
+        to_color_index: u8,
+        block_0d: bool,
+        colors: &[u8; 192],
+        queue: Arc<Queue>,
+        sampler: Arc<Sampler>
+    ) -> Result<Option<ColorWarning>, ColorWarning> {
+        let warning = palette::get_color_warning(to_color_index);
+
+        if block_0d && warning == Some(ColorWarning::DisruptsSync) {
+            return Err(ColorWarning::DisruptsSync);
+        }
+
+        let mut color_indices = self.color_indices;
+        color_indices[which] = to_color_index;
+
+        self.set_color_indices(color_indices, colors, queue, sampler);
+
+        Ok(warning)
+    }
 
-    //     Self {
-    //         color_indices,
-    //         ..self
-    //     }
-    // }
+    pub fn get_warnings(&self) -> Vec<(usize, ColorWarning)> {
+        self.color_indices.iter()
+            .enumerate()
+            .filter_map(|(i, x)| palette::get_color_warning(*x).map(|w| (i, w)))
+            .collect()
+    }
 
     fn get_surface(device: Arc<Device>) -> Surface {
         Surface::new(device.clone(), Vector3::new(0.0, 0.0, 2.0), Vector2::new(52.0, 8.0))
@@ -160,14 +211,11 @@ impl Samples {
         )
     }
 
-    fn get_texture_and_future(color_indices: &[u8; 26], queue: Arc<Queue>) -> (
+    fn get_texture_and_future(color_indices: &[u8; 26], colors: &[u8; 192], grayscale: bool, queue: Arc<Queue>) -> (
         Arc<ImmutableImage<Format>>, CommandBufferExecFuture<NowFuture, AutoCommandBuffer>
     ) {
         let image_data: Vec<u8> = color_indices.iter().flat_map(
-            |x| {
-                let index = (*x * 3) as usize;
-                vec![FULL_PALETTE[index], FULL_PALETTE[index + 1], FULL_PALETTE[index + 2], 255u8]
-            }
+            |x| render::get_flagged_color(*x, palette::get_masked_color_index(*x, grayscale), colors).to_vec()
         ).collect();
 
         ImmutableImage::from_iter(
@@ -191,6 +239,69 @@ impl Samples {
     }
 }
 
+// The top row holds the universal background color followed by the four background sub-palettes,
+// the bottom row repeats it followed by the four sprite sub-palettes
+pub fn get_sub_palette(color_indices: &[u8; 26], which: usize) -> [u8; 4] {
+    let row = which / 4 * 13;
+    let start = row + 1 + which % 4 * 3;
+
+    [
+        color_indices[0],
+        color_indices[start],
+        color_indices[start + 1],
+        color_indices[start + 2],
+    ]
+}
+
+pub fn get_sub_palettes(color_indices: &[u8; 26]) -> Vec<u8> {
+    (0..8).flat_map(|x| get_sub_palette(color_indices, x).to_vec()).collect()
+}
+
+// Inverse of get_sub_palettes, where the universal background color is taken from the first sub-palette
+pub fn get_color_indices(sub_palettes: &[u8]) -> [u8; 26] {
+    let mut color_indices: [u8; 26] = [0u8; 26];
+    color_indices[0] = sub_palettes[0];
+    color_indices[13] = sub_palettes[0];
+
+    for which in 0..8 {
+        let start = which / 4 * 13 + 1 + which % 4 * 3;
+
+        for x in 0..3 {
+            color_indices[start + x] = sub_palettes[which * 4 + 1 + x];
+        }
+    }
+
+    color_indices
+}
+
+// Moves the colors of a sub-palette along with pixels that were remapped, so they keep their color
+// The universal background color is shared, so remaps that move index 0 cannot be followed
+pub fn get_remapped_color_indices(color_indices: &[u8; 26], which: usize, mapping: [u8; 4]) -> Option<[u8; 26]> {
+    if mapping[0] != 0 {
+        return None;
+    }
+
+    let mut sub_palettes = get_sub_palettes(color_indices);
+    let sub_palette = get_sub_palette(color_indices, which);
+
+    for x in 1..4 {
+        sub_palettes[which * 4 + mapping[x] as usize] = sub_palette[x];
+    }
+
+    let mut remapped = get_color_indices(&sub_palettes);
+    remapped[13] = color_indices[13];
+
+    Some(remapped)
+}
+
+// Layout of $3F00-$3F1F, where every first entry mirrors the universal background color
+pub fn get_palette_ram(color_indices: &[u8; 26]) -> [u8; 32] {
+    let mut palette_ram: [u8; 32] = [0u8; 32];
+    palette_ram.copy_from_slice(&get_sub_palettes(color_indices));
+
+    palette_ram
+}
+
 pub mod vs {
     vulkano_shaders::shader!{
     ty: "vertex",
@@ -242,15 +353,27 @@ vec2 color_center = vec2(
 );
 
 void main() {
+    vec4 texel;
+
     if( abs(uv.x - color_center.x) < color_square_size.x / 1.5
      && abs(uv.y - color_center.y) < color_square_size.y / 1.5
       )
     {
-        color = vec4(texture(tex, color_center).xyz, 1.0);
+        texel = texture(tex, color_center);
     }
     else
     {
-        color = vec4(texture(tex, uv).xyz, 1.0);
+        texel = texture(tex, uv);
+    }
+
+    color = vec4(texel.xyz, 1.0);
+
+    // Problematic entries are flagged through the alpha channel
+    vec2 local = fract(uv * total_size);
+
+    if(texel.a < 1.0 && local.x + local.y < 0.4)
+    {
+        color = vec4(1.0, 0.0, 0.0, 1.0);
     }
 }
 "
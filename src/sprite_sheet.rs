@@ -0,0 +1,355 @@
+// Copyright 2019, Sjors van Gelderen
+
+// Slices the frames of a sprite sheet image into hardware sprites, with a metasprite per frame
+
+use crate::chr;
+use crate::color::ColorMatcher;
+use crate::metasprite::{
+    Metasprite,
+    Part,
+};
+use crate::oam::{
+    self,
+    Sprite,
+};
+use crate::samples;
+
+// RGBA pixels of the image that is sliced
+pub struct SpriteSheet {
+    pub width: usize,
+    pub height: usize,
+    image_data: Vec<u8>,
+    key: Option<[u8; 3]>,
+}
+
+// The result of slicing, the pixels still have to be written to the sheet
+pub struct Import {
+    pub pixels: Vec<(usize, usize, u8)>,
+    pub metasprites: Vec<Metasprite>,
+    // The master palette colors of every frame that none of its sprite sub-palettes hold
+    pub unrepresentable: Vec<Vec<u8>>,
+    // Sprites that were left out because there were no free tiles left
+    pub dropped: usize,
+}
+
+impl SpriteSheet {
+    // Images without any transparency take the color of the top left pixel as the background
+    pub fn new(width: usize, height: usize, image_data: Vec<u8>) -> Self {
+        let key = if image_data.chunks(4).any(|x| x[3] < 128) {
+            None
+        }
+        else {
+            Some([image_data[0], image_data[1], image_data[2]])
+        };
+
+        Self {
+            width,
+            height,
+            image_data,
+            key,
+        }
+    }
+
+    pub fn is_opaque(&self, x: usize, y: usize) -> bool {
+        let index = (y * self.width + x) * 4;
+        let pixel = &self.image_data[index..index + 4];
+
+        pixel[3] >= 128 && self.key.map_or(true, |x| pixel[..3] != x)
+    }
+
+    fn get_rgb(&self, x: usize, y: usize) -> [u8; 3] {
+        let index = (y * self.width + x) * 4;
+
+        [self.image_data[index], self.image_data[index + 1], self.image_data[index + 2]]
+    }
+
+    // Cells of the grid that hold any opaque pixels, row by row
+    pub fn get_grid_frames(&self, cell_width: usize, cell_height: usize) -> Vec<(usize, usize, usize, usize)> {
+        let (columns, rows) = (self.width / cell_width, self.height / cell_height);
+
+        (0..columns * rows)
+            .map(|i| (i % columns * cell_width, i / columns * cell_height, cell_width, cell_height))
+            .filter(|&(x0, y0, width, height)| {
+                (0..width * height).any(|i| self.is_opaque(x0 + i % width, y0 + i / width))
+            })
+            .collect()
+    }
+
+    // Bounding boxes of touching opaque pixels, where overlapping boxes are merged into one frame
+    // Frames whose vertical extents overlap form a row, rows are ordered top to bottom and frames left to right
+    pub fn get_detected_frames(&self) -> Vec<(usize, usize, usize, usize)> {
+        let mut visited = vec![false; self.width * self.height];
+        let mut boxes: Vec<(usize, usize, usize, usize)> = vec![];
+
+        for start in 0..self.width * self.height {
+            if visited[start] || !self.is_opaque(start % self.width, start / self.width) {
+                continue;
+            }
+
+            let (mut x0, mut y0, mut x1, mut y1) = (self.width, self.height, 0, 0);
+            let mut stack = vec![start];
+            visited[start] = true;
+
+            while let Some(i) = stack.pop() {
+                let (x, y) = (i % self.width, i / self.width);
+                x0 = x0.min(x);
+                y0 = y0.min(y);
+                x1 = x1.max(x);
+                y1 = y1.max(y);
+
+                for ny in y.saturating_sub(1)..(y + 2).min(self.height) {
+                    for nx in x.saturating_sub(1)..(x + 2).min(self.width) {
+                        let neighbour = ny * self.width + nx;
+
+                        if !visited[neighbour] && self.is_opaque(nx, ny) {
+                            visited[neighbour] = true;
+                            stack.push(neighbour);
+                        }
+                    }
+                }
+            }
+
+            boxes.push((x0, y0, x1 - x0 + 1, y1 - y0 + 1));
+        }
+
+        let boxes = get_merged_boxes(boxes);
+        let mut rows: Vec<Vec<(usize, usize, usize, usize)>> = vec![];
+        let mut bottom = 0;
+
+        for frame in boxes {
+            if rows.is_empty() || frame.1 >= bottom {
+                rows.push(vec![]);
+                bottom = 0;
+            }
+
+            bottom = bottom.max(frame.1 + frame.3);
+            rows.last_mut().unwrap().push(frame);
+        }
+
+        rows.into_iter().flat_map(|mut row| {
+            row.sort_by_key(|x| x.0);
+            row
+        }).collect()
+    }
+
+    // Every frame becomes a metasprite with its origin at the bottom center, tiles are reused where they match
+    // Free tiles are given as sheet tile indices, they are filled in the order they are given
+    pub fn slice(
+        &self,
+        frames: &[(usize, usize, usize, usize)],
+        matcher: &ColorMatcher,
+        colors: &[u8; 192],
+        color_indices: &[u8; 26],
+        pixels: &[u8; 32768],
+        page: usize,
+        tall: bool,
+        free: &[usize]
+    ) -> Import {
+        let height = oam::get_height(tall);
+        let sub_palettes: Vec<[u8; 4]> = (0..4).map(|x| samples::get_sub_palette(color_indices, 4 + x)).collect();
+        let sub_palette_matchers: Vec<ColorMatcher> = sub_palettes.iter()
+            .map(|x| ColorMatcher::from_color_indices(colors, &x[1..], matcher.metric))
+            .collect();
+
+        let get_rgb = |color_index: u8| {
+            let index = color_index as usize * 3;
+            [colors[index], colors[index + 1], colors[index + 2]]
+        };
+
+        let mut sheet = *pixels;
+        let mut free = free.to_vec();
+        let mut import = Import {
+            pixels: vec![],
+            metasprites: vec![],
+            unrepresentable: vec![],
+            dropped: 0,
+        };
+
+        for &(frame_x, frame_y, frame_width, frame_height) in frames {
+            let origin = ((frame_x + frame_width / 2) as i32, (frame_y + frame_height) as i32);
+            let mask: Vec<bool> = (0..frame_width * frame_height)
+                .map(|i| self.is_opaque(frame_x + i % frame_width, frame_y + i / frame_width))
+                .collect();
+
+            let mut parts = vec![];
+            let mut unrepresentable = vec![];
+
+            for (sprite_x, sprite_y) in get_sprite_positions(&mask, frame_width, frame_height, height) {
+                // The nearest color of every opaque pixel of the sprite, left out pixels are transparent
+                let nearest: Vec<Option<(usize, usize, u8)>> = (0..8 * height).map(|i| {
+                    let (x, y) = (sprite_x + i % 8, sprite_y + i / 8);
+
+                    if x < frame_width && y < frame_height && mask[y * frame_width + x] {
+                        let (x, y) = (frame_x + x, frame_y + y);
+                        Some((x, y, matcher.get_nearest(self.get_rgb(x, y))))
+                    }
+                    else {
+                        None
+                    }
+                }).collect();
+
+                let is_held = |color_index: u8, sub_palette: usize| {
+                    sub_palettes[sub_palette][1..].iter().any(|x| get_rgb(*x) == get_rgb(color_index))
+                };
+
+                // The sub-palette that holds the most pixels, lower ones win ties
+                let sub_palette = (0..4).rev().max_by_key(|&x| {
+                    nearest.iter().flatten().filter(|&&(_, _, color_index)| is_held(color_index, x)).count()
+                }).unwrap();
+
+                let wanted: Vec<u8> = nearest.iter().map(|x| match *x {
+                    Some((x, y, color_index)) => {
+                        if !is_held(color_index, sub_palette) {
+                            unrepresentable.push(color_index);
+                        }
+
+                        let held = sub_palette_matchers[sub_palette].get_nearest(self.get_rgb(x, y));
+                        sub_palettes[sub_palette][1..].iter().position(|x| *x == held).unwrap() as u8 + 1
+                    },
+                    None => 0,
+                }).collect();
+
+                let (tile, flip) = match find_tile(&sheet, page, tall, &wanted) {
+                    Some(found) => found,
+                    None => match take_free_tile(&mut free, page, tall) {
+                        Some(tile) => {
+                            let sprite = Sprite { tile, ..Sprite::hidden() };
+
+                            for (i, (tile_page, tile)) in sprite.get_tiles(page, tall).into_iter().enumerate() {
+                                let (x0, y0) = chr::get_tile_origin(tile_page * 256 + tile as usize);
+
+                                for j in 0..64 {
+                                    let (x, y) = (x0 + j % 8, y0 + j / 8);
+                                    let color = wanted[i * 64 + j];
+
+                                    sheet[y * chr::SHEET_WIDTH + x] = color;
+                                    import.pixels.push((x, y, color));
+                                }
+                            }
+
+                            (tile, 0)
+                        },
+                        None => {
+                            import.dropped += 1;
+                            continue;
+                        }
+                    }
+                };
+
+                parts.push(Part {
+                    dx: ((frame_x + sprite_x) as i32 - origin.0).max(-127).min(127) as i8,
+                    dy: ((frame_y + sprite_y) as i32 - origin.1).max(-128).min(127) as i8,
+                    tile,
+                    attributes: sub_palette as u8 | flip,
+                });
+            }
+
+            unrepresentable.sort();
+            unrepresentable.dedup();
+
+            import.metasprites.push(Metasprite::new(parts));
+            import.unrepresentable.push(unrepresentable);
+        }
+
+        import
+    }
+}
+
+// Keeps merging boxes that overlap until none do
+fn get_merged_boxes(mut boxes: Vec<(usize, usize, usize, usize)>) -> Vec<(usize, usize, usize, usize)> {
+    let overlaps = |a: (usize, usize, usize, usize), b: (usize, usize, usize, usize)| {
+        a.0 < b.0 + b.2 && b.0 < a.0 + a.2 && a.1 < b.1 + b.3 && b.1 < a.1 + a.3
+    };
+
+    let mut merged = true;
+
+    while merged {
+        merged = false;
+
+        'search: for i in 0..boxes.len() {
+            for j in i + 1..boxes.len() {
+                if overlaps(boxes[i], boxes[j]) {
+                    let (a, b) = (boxes[i], boxes.remove(j));
+                    let (x0, y0) = (a.0.min(b.0), a.1.min(b.1));
+                    let (x1, y1) = ((a.0 + a.2).max(b.0 + b.2), (a.1 + a.3).max(b.1 + b.3));
+
+                    boxes[i] = (x0, y0, x1 - x0, y1 - y0);
+                    merged = true;
+
+                    break 'search;
+                }
+            }
+        }
+    }
+
+    boxes.sort_by_key(|x| (x.1, x.0));
+    boxes
+}
+
+// Covers the opaque pixels strip by strip from the top, placing every sprite at the leftmost pixel in the strip
+// and then as low as it can go, which is not always the fewest sprites but comes close for most frames
+fn get_sprite_positions(mask: &[bool], width: usize, height: usize, sprite_height: usize) -> Vec<(usize, usize)> {
+    let mut covered = vec![false; mask.len()];
+    let mut positions = vec![];
+
+    let is_left = |covered: &[bool], x: usize, y: usize| mask[y * width + x] && !covered[y * width + x];
+
+    while let Some(first) = (0..mask.len()).find(|&i| mask[i] && !covered[i]) {
+        let top = first / width;
+        let strip = top..(top + sprite_height).min(height);
+
+        let left = (0..width)
+            .find(|&x| strip.clone().any(|y| is_left(&covered, x, y)))
+            .unwrap();
+
+        let top = (top..height)
+            .find(|&y| (left..(left + 8).min(width)).any(|x| is_left(&covered, x, y)))
+            .unwrap();
+
+        for y in top..(top + sprite_height).min(height) {
+            for x in left..(left + 8).min(width) {
+                covered[y * width + x] = true;
+            }
+        }
+
+        positions.push((left, top));
+    }
+
+    positions
+}
+
+// A tile, or a pair of tiles for tall sprites, that shows the pixels with some combination of flips
+fn find_tile(pixels: &[u8; 32768], page: usize, tall: bool, wanted: &[u8]) -> Option<(u8, u8)> {
+    let height = oam::get_height(tall);
+    let flips = [0, oam::FLIP_HORIZONTAL, oam::FLIP_VERTICAL, oam::FLIP_HORIZONTAL | oam::FLIP_VERTICAL];
+
+    // Tall sprites take their page from the tile index, so every index is a different pair
+    for tile in 0..256 {
+        for &flip in flips.iter() {
+            let sprite = Sprite { tile: tile as u8, attributes: flip, ..Sprite::hidden() };
+            let matches = (0..8 * height).all(|i| sprite.get_pixel(pixels, page, i % 8, i / 8, tall) == wanted[i]);
+
+            if matches {
+                return Some((tile as u8, flip));
+            }
+        }
+    }
+
+    None
+}
+
+// Takes a free tile on the page, or for tall sprites a free pair on either page
+fn take_free_tile(free: &mut Vec<usize>, page: usize, tall: bool) -> Option<u8> {
+    if tall {
+        let first = free.iter().cloned().find(|&x| x % 2 == 0 && free.contains(&(x + 1)))?;
+        free.retain(|&x| x != first && x != first + 1);
+
+        Some((first % 256) as u8 | (first / 256) as u8)
+    }
+    else {
+        let first = free.iter().cloned().find(|&x| x / 256 == page)?;
+        free.retain(|&x| x != first);
+
+        Some((first % 256) as u8)
+    }
+}
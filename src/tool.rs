@@ -1,11 +1,131 @@
 // Copyright 2019, Sjors van Gelderen
 
-// enum Tool {
-//     Pencil,
-//     Brush,
-//     Line,
-//     Rectangle,
-//     RectangleFill,
-//     Ellipse,
-//     EllipseFill,
-// }
\ No newline at end of file
+use crate::chr::FillMode;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tool {
+    Pencil,
+    Brush,
+    Line,
+    Rectangle,
+    RectangleFill,
+    Ellipse,
+    EllipseFill,
+    Fill(FillMode),
+    Select,
+    // Moves a tile to where it is dropped, or swaps both tiles with shift held
+    Reorder,
+}
+
+impl Tool {
+    // Freehand tools draw while the mouse moves, shapes are drawn on release
+    pub fn is_freehand(self) -> bool {
+        self == Tool::Pencil || self == Tool::Brush
+    }
+
+    pub fn get_points(self, from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+        match self {
+            Tool::Pencil | Tool::Line => get_line(from, to),
+            Tool::Brush => get_line(from, to).iter().flat_map(|&(x, y)| {
+                vec![(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)]
+            }).collect(),
+            Tool::Rectangle => get_rectangle(from, to, false),
+            Tool::RectangleFill => get_rectangle(from, to, true),
+            Tool::Ellipse => get_ellipse(from, to, false),
+            Tool::EllipseFill => get_ellipse(from, to, true),
+            // Fills depend on the pixels and are applied through the pattern table
+            Tool::Fill(_) => vec![to],
+            // Selections snap to tiles and never draw
+            Tool::Select | Tool::Reorder => vec![],
+        }
+    }
+}
+
+// Bresenham
+pub fn get_line(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+    let dx = (to.0 - from.0).abs();
+    let dy = -(to.1 - from.1).abs();
+    let sx = if from.0 < to.0 { 1 } else { -1 };
+    let sy = if from.1 < to.1 { 1 } else { -1 };
+
+    let mut points = vec![];
+    let (mut x, mut y) = from;
+    let mut error = dx + dy;
+
+    loop {
+        points.push((x, y));
+
+        if x == to.0 && y == to.1 {
+            break;
+        }
+
+        let e2 = error * 2;
+
+        if e2 >= dy {
+            error += dy;
+            x += sx;
+        }
+
+        if e2 <= dx {
+            error += dx;
+            y += sy;
+        }
+    }
+
+    points
+}
+
+pub fn get_rectangle(from: (i32, i32), to: (i32, i32), fill: bool) -> Vec<(i32, i32)> {
+    let (x0, x1) = (from.0.min(to.0), from.0.max(to.0));
+    let (y0, y1) = (from.1.min(to.1), from.1.max(to.1));
+
+    let mut points = vec![];
+
+    for y in y0..=y1 {
+        for x in x0..=x1 {
+            if fill || x == x0 || x == x1 || y == y0 || y == y1 {
+                points.push((x, y));
+            }
+        }
+    }
+
+    points
+}
+
+// Fits the ellipse inside the rectangle spanned by both points
+pub fn get_ellipse(from: (i32, i32), to: (i32, i32), fill: bool) -> Vec<(i32, i32)> {
+    let (x0, x1) = (from.0.min(to.0), from.0.max(to.0));
+    let (y0, y1) = (from.1.min(to.1), from.1.max(to.1));
+
+    let center = ((x0 + x1) as f32 / 2.0, (y0 + y1) as f32 / 2.0);
+    let radius = ((x1 - x0) as f32 / 2.0 + 0.5, (y1 - y0) as f32 / 2.0 + 0.5);
+
+    let is_inside = |x: i32, y: i32| {
+        let nx = (x as f32 - center.0) / radius.0;
+        let ny = (y as f32 - center.1) / radius.1;
+
+        nx * nx + ny * ny <= 1.0
+    };
+
+    let mut points = vec![];
+
+    for y in y0..=y1 {
+        for x in x0..=x1 {
+            if !is_inside(x, y) {
+                continue;
+            }
+
+            // The outline consists of the inner pixels that border the outside
+            let is_edge = !is_inside(x - 1, y)
+                || !is_inside(x + 1, y)
+                || !is_inside(x, y - 1)
+                || !is_inside(x, y + 1);
+
+            if fill || is_edge {
+                points.push((x, y));
+            }
+        }
+    }
+
+    points
+}